@@ -2,4 +2,11 @@
 
 pub mod prelude;
 
+pub mod invitation;
+pub mod login_event;
+pub mod login_throttle;
+pub mod mfa_recovery_code;
+pub mod password_reset_token;
+pub mod revoked_token;
+pub mod token_family;
 pub mod user;
@@ -1,3 +1,10 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.0
 
+pub use super::invitation::Entity as Invitation;
+pub use super::login_event::Entity as LoginEvent;
+pub use super::login_throttle::Entity as LoginThrottle;
+pub use super::mfa_recovery_code::Entity as MfaRecoveryCode;
+pub use super::password_reset_token::Entity as PasswordResetToken;
+pub use super::revoked_token::Entity as RevokedToken;
+pub use super::token_family::Entity as TokenFamily;
 pub use super::user::Entity as User;
@@ -1,6 +1,7 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.0
 
 use sea_orm::entity::prelude::*;
+use sea_orm::ActiveValue::{NotSet, Set};
 use serde::Serialize;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize)]
@@ -8,23 +9,113 @@ use serde::Serialize;
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
-    #[sea_orm(unique)]
     pub username: Option<String>,
     pub firstname: Option<String>,
     pub lastname: Option<String>,
-    #[sea_orm(unique)]
     pub email: Option<String>,
+    #[serde(skip_serializing)]
     pub password: Option<String>,
     pub is_active: Option<bool>,
-    pub last_login: Option<DateTime>,
-    pub date_joined: Option<DateTime>,
-    pub created_at: Option<DateTime>,
-    pub updated_at: Option<DateTime>,
+    pub last_login: Option<DateTimeWithTimeZone>,
+    pub date_joined: Option<DateTimeWithTimeZone>,
+    pub created_at: Option<DateTimeWithTimeZone>,
+    pub updated_at: Option<DateTimeWithTimeZone>,
     pub is_admin: Option<bool>,
     pub is_superadmin: Option<bool>,
+    #[serde(skip_serializing)]
+    pub tokens_valid_after: Option<DateTimeWithTimeZone>,
+    pub version: i32,
+    pub deleted_at: Option<DateTimeWithTimeZone>,
+    #[serde(skip_serializing)]
+    pub mfa_secret: Option<String>,
+    pub mfa_enabled_at: Option<DateTimeWithTimeZone>,
+    #[serde(skip_serializing)]
+    pub mfa_last_step: Option<i64>,
+    pub mfa_required: Option<bool>,
+    pub pending_email: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::login_event::Entity")]
+    LoginEvent,
+    #[sea_orm(has_many = "super::mfa_recovery_code::Entity")]
+    MfaRecoveryCode,
+    #[sea_orm(has_many = "super::password_reset_token::Entity")]
+    PasswordResetToken,
+    #[sea_orm(has_many = "super::revoked_token::Entity")]
+    RevokedToken,
+    #[sea_orm(has_many = "super::token_family::Entity")]
+    TokenFamily,
+}
+
+impl Related<super::login_event::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LoginEvent.def()
+    }
+}
+
+impl Related<super::mfa_recovery_code::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::MfaRecoveryCode.def()
+    }
+}
+
+impl Related<super::password_reset_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PasswordResetToken.def()
+    }
+}
+
+impl Related<super::revoked_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RevokedToken.def()
+    }
+}
+
+impl Related<super::token_family::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::TokenFamily.def()
+    }
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    /// The timestamps are always set by the server, whatever the caller put in them:
+    /// `date_joined` and `created_at` once on insert, `updated_at` on every save. Usernames and
+    /// emails are stored lowercase, so `Alice` and `alice` are the same user.
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let now = chrono::Utc::now().fixed_offset();
+        if insert {
+            self.date_joined = Set(Some(now));
+            self.created_at = Set(Some(now));
+        } else {
+            self.date_joined = NotSet;
+            self.created_at = NotSet;
+        }
+        self.updated_at = Set(Some(now));
 
-impl ActiveModelBehavior for ActiveModel {}
+        if let Set(Some(username)) = &self.username {
+            self.username = Set(Some(username.to_lowercase()));
+        }
+        if let Set(Some(email)) = &self.email {
+            self.email = Set(Some(email.to_lowercase()));
+        }
+        if let Set(Some(email)) = &self.pending_email {
+            self.pending_email = Set(Some(email.to_lowercase()));
+        }
+
+        Ok(self)
+    }
+}
+
+impl Entity {
+    /// Users that have not been soft deleted. Use this instead of `find` unless deleted users
+    /// are wanted too.
+    pub fn find_existing() -> Select<Entity> {
+        Self::find().filter(Column::DeletedAt.is_null())
+    }
+}
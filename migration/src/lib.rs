@@ -2,6 +2,22 @@ pub use sea_orm_migration::prelude::*;
 
 mod m20240913_193712_create_user_table;
 mod m20240916_144220_alter_user_table_add_admin_fields;
+mod m20261018_100000_create_token_family_table;
+mod m20261018_110000_alter_user_table_add_tokens_valid_after;
+mod m20261018_120000_create_revoked_token_table;
+mod m20261018_130000_alter_user_table_add_search_columns;
+mod m20261018_140000_alter_user_table_add_version;
+mod m20261018_150000_alter_user_table_add_deleted_at;
+mod m20261018_160000_alter_user_table_timestamps_with_time_zone;
+mod m20261018_170000_create_login_event_table;
+mod m20261018_180000_create_login_throttle_table;
+mod m20261018_190000_alter_user_table_case_insensitive_identifiers;
+mod m20261018_200000_create_password_reset_token_table;
+mod m20261018_203000_activate_existing_users;
+mod m20261018_206000_alter_user_table_add_pending_email;
+mod m20261018_210000_create_invitation_table;
+mod m20261018_220000_alter_user_table_add_mfa_columns;
+mod m20261018_230000_create_mfa_recovery_code_table;
 
 pub struct Migrator;
 
@@ -11,6 +27,22 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(m20240913_193712_create_user_table::Migration),
             Box::new(m20240916_144220_alter_user_table_add_admin_fields::Migration),
+            Box::new(m20261018_100000_create_token_family_table::Migration),
+            Box::new(m20261018_110000_alter_user_table_add_tokens_valid_after::Migration),
+            Box::new(m20261018_120000_create_revoked_token_table::Migration),
+            Box::new(m20261018_130000_alter_user_table_add_search_columns::Migration),
+            Box::new(m20261018_140000_alter_user_table_add_version::Migration),
+            Box::new(m20261018_150000_alter_user_table_add_deleted_at::Migration),
+            Box::new(m20261018_160000_alter_user_table_timestamps_with_time_zone::Migration),
+            Box::new(m20261018_170000_create_login_event_table::Migration),
+            Box::new(m20261018_180000_create_login_throttle_table::Migration),
+            Box::new(m20261018_190000_alter_user_table_case_insensitive_identifiers::Migration),
+            Box::new(m20261018_200000_create_password_reset_token_table::Migration),
+            Box::new(m20261018_203000_activate_existing_users::Migration),
+            Box::new(m20261018_206000_alter_user_table_add_pending_email::Migration),
+            Box::new(m20261018_210000_create_invitation_table::Migration),
+            Box::new(m20261018_220000_alter_user_table_add_mfa_columns::Migration),
+            Box::new(m20261018_230000_create_mfa_recovery_code_table::Migration),
         ]
     }
 }
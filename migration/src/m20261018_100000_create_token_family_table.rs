@@ -0,0 +1,66 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(TokenFamily::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(TokenFamily::Id).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(TokenFamily::UserId).integer().not_null())
+                    .col(ColumnDef::new(TokenFamily::CurrentJti).uuid().not_null())
+                    .col(ColumnDef::new(TokenFamily::ExpiresAt).timestamp_with_time_zone().not_null())
+                    .col(ColumnDef::new(TokenFamily::RevokedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(TokenFamily::CreatedAt).timestamp_with_time_zone().not_null())
+                    .col(ColumnDef::new(TokenFamily::UpdatedAt).timestamp_with_time_zone().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_token_family_user_id")
+                            .from(TokenFamily::Table, TokenFamily::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_token_family_user_id")
+                    .table(TokenFamily::Table)
+                    .col(TokenFamily::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(TokenFamily::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum TokenFamily {
+    Table,
+    Id,
+    UserId,
+    CurrentJti,
+    ExpiresAt,
+    RevokedAt,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
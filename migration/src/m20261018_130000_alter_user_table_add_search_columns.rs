@@ -0,0 +1,84 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("CREATE EXTENSION IF NOT EXISTS pg_trgm")
+            .await?;
+
+        // Both columns are computed by Postgres and deliberately left out of the entity. The
+        // `simple` configuration keeps names from being stemmed.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(User::SearchVector)
+                            .custom(Alias::new("tsvector"))
+                            .extra(
+                                "GENERATED ALWAYS AS (\
+                                    setweight(to_tsvector('simple', coalesce(username, '')), 'A') || \
+                                    setweight(to_tsvector('simple', coalesce(firstname, '') || ' ' || coalesce(lastname, '')), 'B') || \
+                                    setweight(to_tsvector('simple', coalesce(email, '')), 'C')\
+                                ) STORED",
+                            ),
+                    )
+                    .add_column_if_not_exists(
+                        ColumnDef::new(User::SearchText)
+                            .text()
+                            .extra(
+                                "GENERATED ALWAYS AS (lower(\
+                                    coalesce(username, '') || ' ' || coalesce(firstname, '') || ' ' || \
+                                    coalesce(lastname, '') || ' ' || coalesce(email, '')\
+                                )) STORED",
+                            ),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_user_search_vector")
+                    .table(User::Table)
+                    .col(User::SearchVector)
+                    .full_text()
+                    .to_owned(),
+            )
+            .await?;
+
+        // sea-query has no syntax for operator classes
+        manager
+            .get_connection()
+            .execute_unprepared(r#"CREATE INDEX IF NOT EXISTS "idx_user_search_text" ON "user" USING GIN (search_text gin_trgm_ops)"#)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::SearchVector)
+                    .drop_column(User::SearchText)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    SearchVector,
+    SearchText,
+}
@@ -0,0 +1,80 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column_if_not_exists(ColumnDef::new(User::DeletedAt).date_time())
+                    .to_owned(),
+            )
+            .await?;
+
+        // usernames and emails of deleted users can be taken again, so uniqueness only holds
+        // among the rows that are not deleted
+        let db = manager.get_connection();
+        db.execute_unprepared(r#"ALTER TABLE "user" DROP CONSTRAINT IF EXISTS "user_username_key""#).await?;
+        db.execute_unprepared(r#"ALTER TABLE "user" DROP CONSTRAINT IF EXISTS "user_email_key""#).await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_user_username_not_deleted")
+                    .table(User::Table)
+                    .col(User::Username)
+                    .unique()
+                    .and_where(Expr::col(User::DeletedAt).is_null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_user_email_not_deleted")
+                    .table(User::Table)
+                    .col(User::Email)
+                    .unique()
+                    .and_where(Expr::col(User::DeletedAt).is_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(Index::drop().name("idx_user_email_not_deleted").table(User::Table).to_owned())
+            .await?;
+        manager
+            .drop_index(Index::drop().name("idx_user_username_not_deleted").table(User::Table).to_owned())
+            .await?;
+
+        // fails when a deleted user shares a username or email with another user
+        let db = manager.get_connection();
+        db.execute_unprepared(r#"ALTER TABLE "user" ADD CONSTRAINT "user_username_key" UNIQUE ("username")"#).await?;
+        db.execute_unprepared(r#"ALTER TABLE "user" ADD CONSTRAINT "user_email_key" UNIQUE ("email")"#).await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::DeletedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Username,
+    Email,
+    DeletedAt,
+}
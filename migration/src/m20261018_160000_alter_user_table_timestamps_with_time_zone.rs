@@ -0,0 +1,57 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// The timestamp columns of `user`, all of which were stored as UTC without a time zone.
+const COLUMNS: &[&str] = &["last_login", "date_joined", "created_at", "updated_at", "tokens_valid_after", "deleted_at"];
+
+/// The columns the database fills in when an insert leaves them out.
+const DEFAULT_NOW: &[&str] = &["date_joined", "created_at", "updated_at"];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        for column in COLUMNS {
+            db.execute_unprepared(&format!(
+                r#"ALTER TABLE "user" ALTER COLUMN "{0}" TYPE timestamptz USING "{0}" AT TIME ZONE 'UTC'"#,
+                column
+            ))
+            .await?;
+        }
+        for column in DEFAULT_NOW {
+            db.execute_unprepared(&format!(r#"ALTER TABLE "user" ALTER COLUMN "{}" SET DEFAULT now()"#, column))
+                .await?;
+        }
+
+        // rows written before the server took over the timestamps may lack them
+        db.execute_unprepared(
+            r#"UPDATE "user" SET
+                created_at = coalesce(created_at, date_joined, now()),
+                date_joined = coalesce(date_joined, created_at, now()),
+                updated_at = coalesce(updated_at, created_at, date_joined, now())
+            WHERE created_at IS NULL OR date_joined IS NULL OR updated_at IS NULL"#,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        for column in DEFAULT_NOW {
+            db.execute_unprepared(&format!(r#"ALTER TABLE "user" ALTER COLUMN "{}" DROP DEFAULT"#, column))
+                .await?;
+        }
+        for column in COLUMNS {
+            db.execute_unprepared(&format!(
+                r#"ALTER TABLE "user" ALTER COLUMN "{0}" TYPE timestamp USING "{0}" AT TIME ZONE 'UTC'"#,
+                column
+            ))
+            .await?;
+        }
+
+        Ok(())
+    }
+}
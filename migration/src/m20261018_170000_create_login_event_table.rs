@@ -0,0 +1,72 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(LoginEvent::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(LoginEvent::Id).big_integer().not_null().auto_increment().primary_key())
+                    .col(ColumnDef::new(LoginEvent::UserId).integer())
+                    .col(ColumnDef::new(LoginEvent::Username).string().not_null())
+                    .col(ColumnDef::new(LoginEvent::Outcome).string().not_null())
+                    .col(ColumnDef::new(LoginEvent::Ip).string())
+                    .col(ColumnDef::new(LoginEvent::UserAgent).string())
+                    .col(
+                        ColumnDef::new(LoginEvent::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_login_event_user_id")
+                            .from(LoginEvent::Table, LoginEvent::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_login_event_user_id_created_at")
+                    .table(LoginEvent::Table)
+                    .col(LoginEvent::UserId)
+                    .col(LoginEvent::CreatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(LoginEvent::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum LoginEvent {
+    Table,
+    Id,
+    UserId,
+    Username,
+    Outcome,
+    Ip,
+    UserAgent,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
@@ -0,0 +1,47 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(LoginThrottle::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(LoginThrottle::Key).string().not_null().primary_key())
+                    .col(ColumnDef::new(LoginThrottle::Failures).integer().not_null())
+                    .col(ColumnDef::new(LoginThrottle::LastFailureAt).timestamp_with_time_zone().not_null())
+                    .col(ColumnDef::new(LoginThrottle::LockedUntil).timestamp_with_time_zone())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_login_throttle_last_failure_at")
+                    .table(LoginThrottle::Table)
+                    .col(LoginThrottle::LastFailureAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(LoginThrottle::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum LoginThrottle {
+    Table,
+    Key,
+    Failures,
+    LastFailureAt,
+    LockedUntil,
+}
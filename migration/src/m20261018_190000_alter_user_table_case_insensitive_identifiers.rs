@@ -0,0 +1,97 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, Statement};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // lowercasing would merge these, so they have to be renamed or deleted by hand first
+        let mut conflicts = vec![];
+        for column in ["username", "email"] {
+            let sql = format!(
+                r#"SELECT lower("{column}") AS value, string_agg("id"::text, ', ' ORDER BY "id") AS ids
+                FROM "user" WHERE "deleted_at" IS NULL
+                GROUP BY lower("{column}") HAVING count(*) > 1"#
+            );
+            for row in db.query_all(Statement::from_string(manager.get_database_backend(), sql)).await? {
+                let value: String = row.try_get("", "value")?;
+                let ids: String = row.try_get("", "ids")?;
+                conflicts.push(format!("{} `{}` is used by users {}", column, value, ids));
+            }
+        }
+        if !conflicts.is_empty() {
+            return Err(DbErr::Migration(format!(
+                "usernames and emails must be unique regardless of case: {}",
+                conflicts.join("; ")
+            )));
+        }
+
+        db.execute_unprepared(r#"UPDATE "user" SET "username" = lower("username"), "email" = lower("email")"#).await?;
+
+        manager
+            .drop_index(Index::drop().name("idx_user_username_not_deleted").table(User::Table).to_owned())
+            .await?;
+        manager
+            .drop_index(Index::drop().name("idx_user_email_not_deleted").table(User::Table).to_owned())
+            .await?;
+
+        // values are lowercased on write, the indexes hold even for rows written some other way
+        db.execute_unprepared(
+            r#"CREATE UNIQUE INDEX IF NOT EXISTS "idx_user_lower_username_not_deleted" ON "user" (lower("username")) WHERE "deleted_at" IS NULL"#,
+        )
+        .await?;
+        db.execute_unprepared(
+            r#"CREATE UNIQUE INDEX IF NOT EXISTS "idx_user_lower_email_not_deleted" ON "user" (lower("email")) WHERE "deleted_at" IS NULL"#,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // the original case of lowercased values is gone, they stay lowercase
+        manager
+            .drop_index(Index::drop().name("idx_user_lower_email_not_deleted").table(User::Table).to_owned())
+            .await?;
+        manager
+            .drop_index(Index::drop().name("idx_user_lower_username_not_deleted").table(User::Table).to_owned())
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_user_username_not_deleted")
+                    .table(User::Table)
+                    .col(User::Username)
+                    .unique()
+                    .and_where(Expr::col(User::DeletedAt).is_null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_user_email_not_deleted")
+                    .table(User::Table)
+                    .col(User::Email)
+                    .unique()
+                    .and_where(Expr::col(User::DeletedAt).is_null())
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Username,
+    Email,
+    DeletedAt,
+}
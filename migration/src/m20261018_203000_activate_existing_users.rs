@@ -0,0 +1,25 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // `is_active` used to default to false and was never checked, so most accounts from
+        // before email verification are inactive without anyone having been sent a link.
+        // They keep working as they did; only accounts created from now on start inactive.
+        // Soft deleted accounts stay as they are, restoring one doesn't change `is_active`.
+        manager
+            .get_connection()
+            .execute_unprepared(r#"UPDATE "user" SET "is_active" = true WHERE "is_active" IS NOT TRUE AND "deleted_at" IS NULL"#)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // which accounts were inactive before is not known anymore
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Invitation::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Invitation::Id).integer().not_null().auto_increment().primary_key())
+                    .col(ColumnDef::new(Invitation::Email).string().not_null())
+                    .col(ColumnDef::new(Invitation::Role).string().not_null())
+                    .col(ColumnDef::new(Invitation::TokenHash).string().not_null().unique_key())
+                    .col(ColumnDef::new(Invitation::InvitedBy).integer())
+                    .col(ColumnDef::new(Invitation::AcceptedBy).integer())
+                    .col(ColumnDef::new(Invitation::ExpiresAt).timestamp_with_time_zone().not_null())
+                    .col(ColumnDef::new(Invitation::AcceptedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(Invitation::RevokedAt).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(Invitation::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    // invitations outlive the admins who sent them and the users they became
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_invitation_invited_by")
+                            .from(Invitation::Table, Invitation::InvitedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_invitation_accepted_by")
+                            .from(Invitation::Table, Invitation::AcceptedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_invitation_email")
+                    .table(Invitation::Table)
+                    .col(Invitation::Email)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Invitation::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Invitation {
+    Table,
+    Id,
+    Email,
+    Role,
+    TokenHash,
+    InvitedBy,
+    AcceptedBy,
+    ExpiresAt,
+    AcceptedAt,
+    RevokedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
@@ -0,0 +1,46 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // `mfa_secret` is set by enrollment and only counts once `mfa_enabled_at` is set too,
+        // `mfa_last_step` is the time step of the last accepted code, so no code works twice
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column_if_not_exists(ColumnDef::new(User::MfaSecret).string())
+                    .add_column_if_not_exists(ColumnDef::new(User::MfaEnabledAt).timestamp_with_time_zone())
+                    .add_column_if_not_exists(ColumnDef::new(User::MfaLastStep).big_integer())
+                    .add_column_if_not_exists(ColumnDef::new(User::MfaRequired).boolean().default(false))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::MfaSecret)
+                    .drop_column(User::MfaEnabledAt)
+                    .drop_column(User::MfaLastStep)
+                    .drop_column(User::MfaRequired)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    MfaSecret,
+    MfaEnabledAt,
+    MfaLastStep,
+    MfaRequired,
+}
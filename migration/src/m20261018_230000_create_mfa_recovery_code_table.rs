@@ -0,0 +1,68 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(MfaRecoveryCode::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(MfaRecoveryCode::Id).integer().not_null().auto_increment().primary_key())
+                    .col(ColumnDef::new(MfaRecoveryCode::UserId).integer().not_null())
+                    .col(ColumnDef::new(MfaRecoveryCode::CodeHash).string().not_null())
+                    .col(ColumnDef::new(MfaRecoveryCode::UsedAt).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(MfaRecoveryCode::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_mfa_recovery_code_user_id")
+                            .from(MfaRecoveryCode::Table, MfaRecoveryCode::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_mfa_recovery_code_user_id_code_hash")
+                    .table(MfaRecoveryCode::Table)
+                    .col(MfaRecoveryCode::UserId)
+                    .col(MfaRecoveryCode::CodeHash)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(MfaRecoveryCode::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum MfaRecoveryCode {
+    Table,
+    Id,
+    UserId,
+    CodeHash,
+    UsedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
@@ -0,0 +1,73 @@
+use crate::utils::app_state::AppState;
+use crate::utils::auth::Claims;
+use crate::utils::errors::AppError;
+use actix_web::dev::Payload;
+use actix_web::web::Data;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use entity::user::{Column, Entity as User};
+use sea_orm::{ColumnTrait, QueryFilter};
+use std::future::Future;
+use std::pin::Pin;
+
+/// The user behind the token that `authenticate` accepted. Roles are read from the database
+/// on every request, so revoking `is_admin` takes effect without waiting for tokens to expire.
+#[derive(Clone, Debug)]
+pub struct CurrentUser {
+    pub id: i32,
+    pub is_admin: bool,
+    pub is_superadmin: bool,
+}
+
+impl CurrentUser {
+    pub fn require_admin(&self) -> Result<(), AppError> {
+        if self.is_admin || self.is_superadmin {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden("Admin privileges are required".to_string()))
+        }
+    }
+
+    pub fn require_superadmin(&self) -> Result<(), AppError> {
+        if self.is_superadmin {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden("Superadmin privileges are required".to_string()))
+        }
+    }
+
+    pub fn require_self_or_admin(&self, id: i32) -> Result<(), AppError> {
+        if self.id == id || self.is_admin || self.is_superadmin {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden("You can only access your own account".to_string()))
+        }
+    }
+}
+
+impl FromRequest for CurrentUser {
+    type Error = AppError;
+    type Future = Pin<Box<dyn Future<Output=Result<Self, Self::Error>>>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let user_id = request.extensions().get::<Claims>().map(|claims| claims.id);
+        let app_state = request.app_data::<Data<AppState>>().cloned();
+
+        Box::pin(async move {
+            // only reachable behind `authenticate`, which puts the claims in place
+            let user_id = user_id.ok_or(AppError::MissingToken)?;
+            let app_state = app_state.expect("AppState is not configured");
+
+            let user = User::find_existing()
+                .filter(Column::Id.eq(user_id))
+                .one(&app_state.db)
+                .await?
+                .ok_or(AppError::UnknownUser)?;
+
+            Ok(CurrentUser {
+                id: user.id,
+                is_admin: user.is_admin.unwrap_or(false),
+                is_superadmin: user.is_superadmin.unwrap_or(false),
+            })
+        })
+    }
+}
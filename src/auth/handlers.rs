@@ -1,26 +1,44 @@
+use crate::auth::middlewares::authenticate;
 use crate::auth::models::RefreshToken;
-use crate::utils::auth::JSONWebToken;
-use crate::utils::config::get_secret;
+use crate::auth::tokens;
+use crate::utils::app_state::AppState;
+use crate::utils::auth::{Claims, JSONWebToken, TokenType};
+use crate::utils::config::get_jwt_keys;
+use crate::utils::errors::AppError;
 use crate::utils::response::ApiResponse;
 
-use actix_web::web::Json;
-use actix_web::{post, Error, HttpResponse, Responder};
+use actix_web::middleware::from_fn;
+use actix_web::web::{Data, Json, ReqData};
+use actix_web::{get, post, HttpResponse, Responder};
 
 
 #[post("/refresh")]
-pub async fn refresh_jwt(payload: Json<RefreshToken>) -> Result<impl Responder, Error> {
+pub async fn refresh_jwt(payload: Json<RefreshToken>, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
     let token = payload.token.clone();
-    let jwt = JSONWebToken { secret: get_secret() };
-    match jwt.decode(token) {
-        Ok(data) => {
-            let id = data.claims.id;
-            let email = data.claims.email;
-            let tokens = jwt.encode(id, email);
-            Ok(HttpResponse::Ok().json(tokens))
-        }
-        Err(err) => {
-            let response = ApiResponse { message: err.to_string() };
-            Ok(HttpResponse::BadRequest().json(response))
-        }
-    }
-}
\ No newline at end of file
+    let jwt = JSONWebToken { keys: get_jwt_keys() };
+    let data = jwt.decode(token, TokenType::Refresh).map_err(AppError::InvalidToken)?;
+    let tokens = tokens::rotate(&app_state.db, data.claims).await?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+#[post("/logout", wrap = "from_fn(authenticate)")]
+pub async fn logout(claims: ReqData<Claims>, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    app_state.revocations.revoke(&app_state.db, &claims).await?;
+
+    let response = ApiResponse { message: "Logged out".to_string() };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[post("/logout-all", wrap = "from_fn(authenticate)")]
+pub async fn logout_all(claims: ReqData<Claims>, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    app_state.revocations.revoke_all(&app_state.db, claims.id).await?;
+
+    let response = ApiResponse { message: "Logged out of all sessions".to_string() };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[get("/.well-known/jwks.json")]
+pub async fn jwks() -> Result<impl Responder, AppError> {
+    Ok(HttpResponse::Ok().json(get_jwt_keys().jwks()))
+}
@@ -1,83 +1,59 @@
-use crate::utils::response::ApiResponse;
-use crate::utils::auth::JSONWebToken;
-use crate::utils::config::get_secret;
+use crate::utils::app_state::AppState;
+use crate::utils::auth::{JSONWebToken, TokenType};
+use crate::utils::config::get_jwt_keys;
+use crate::utils::errors::AppError;
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::error::Error;
 use actix_web::http::header::AUTHORIZATION;
 use actix_web::middleware::Next;
-use actix_web::{HttpMessage, HttpResponse, ResponseError};
-use std::fmt;
+use actix_web::web::Data;
+use actix_web::HttpMessage;
 
 pub async fn authenticate(request: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
-    let auth = request.headers().get(AUTHORIZATION);
-    match auth {
-        None => {
-            log::error!("auth token NOT provided");
-            Err(AuthenticationError::MissingToken.into())
-        }
-        Some(header) => {
-            let token = header.to_str().map_err(|_| AuthenticationError::InvalidTokenFormat(Default::default()))?;
-            let token = token.replace("Bearer ", "").to_owned();
-            let jwt = JSONWebToken { secret: get_secret() }; // Consider secure key access
-
-            match jwt.decode(token) {
-                Ok(data) => {
-                    request.extensions_mut().insert(data.claims);
-                    next.call(request).await
-                }
-                Err(err) => {
-                    log::error!("Error decoding token: {}", err);
-                    Err(AuthenticationError::InvalidToken(err).into())
-                }
-            }
-        }
+    if !request.headers().contains_key(AUTHORIZATION) {
+        log::error!("auth token NOT provided");
+        return Err(AppError::MissingToken.into());
     }
-}
-
 
-#[derive(Debug)]
-pub enum AuthenticationError {
-    MissingToken,
-    InvalidTokenFormat(fmt::Error),
-    InvalidToken(jsonwebtoken::errors::Error),
+    verify_token(&request).await?;
+    next.call(request).await
 }
 
-impl fmt::Display for AuthenticationError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            AuthenticationError::MissingToken => write!(f, "Missing authentication token"),
-            AuthenticationError::InvalidTokenFormat(err) => write!(f, "Invalid token format: {}", err),
-            AuthenticationError::InvalidToken(err) => write!(f, "Invalid token: {}", err),
-        }
+/// Like `authenticate`, but lets requests without a token through anonymously. Used on public
+/// routes whose response depends on who is asking.
+pub async fn identify(request: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if request.headers().contains_key(AUTHORIZATION) {
+        verify_token(&request).await?;
     }
-}
 
-// Implement ResponseError for AuthenticationError
-impl ResponseError for AuthenticationError {
-    fn status_code(&self) -> actix_web::http::StatusCode {
-        match self {
-            AuthenticationError::MissingToken => actix_web::http::StatusCode::UNAUTHORIZED,
-            AuthenticationError::InvalidTokenFormat(_) => actix_web::http::StatusCode::BAD_REQUEST,
-            AuthenticationError::InvalidToken(_) => actix_web::http::StatusCode::UNAUTHORIZED,
-        }
-    }
+    next.call(request).await
+}
 
-    fn error_response(&self) -> HttpResponse {
-        let api_response = match self {
-            AuthenticationError::MissingToken => {
-                ApiResponse { message: "Missing authentication token".to_string() }
+// validates the bearer token and makes its `Claims` available to handlers
+async fn verify_token(request: &ServiceRequest) -> Result<(), AppError> {
+    let header = request.headers().get(AUTHORIZATION).ok_or(AppError::MissingToken)?;
+    let token = header.to_str().map_err(|_| AppError::InvalidTokenFormat)?;
+    let token = token.replace("Bearer ", "").to_owned();
+    let jwt = JSONWebToken { keys: get_jwt_keys() };
+
+    match jwt.decode(token, TokenType::Access) {
+        Ok(data) => {
+            let app_state = request.app_data::<Data<AppState>>().expect("AppState is not configured");
+            let account = app_state.revocations.account(&app_state.db, data.claims.id).await?.ok_or(AppError::UnknownUser)?;
+            if !account.is_active {
+                return Err(AppError::InactiveUser);
             }
-            AuthenticationError::InvalidTokenFormat(err) => {
-                ApiResponse { message: format!("Invalid token format: {}", err) }
+            if app_state.revocations.is_revoked(&app_state.db, &data.claims).await? {
+                return Err(AppError::RevokedToken);
             }
-            AuthenticationError::InvalidToken(err) => {
-                ApiResponse { message: format!("Invalid token: {}", err) }
-            }
-        };
 
-        HttpResponse::build(self.status_code())
-            .content_type("application/json")
-            .body(serde_json::to_string(&api_response).unwrap()) // Convert to JSON string
+            request.extensions_mut().insert(data.claims);
+            Ok(())
+        }
+        Err(err) => {
+            log::error!("Error decoding token: {}", err);
+            Err(AppError::InvalidToken(err))
+        }
     }
 }
@@ -4,4 +4,8 @@ mod models;
 // public modules
 pub mod handlers;
 pub mod urls;
-pub mod middlewares;
\ No newline at end of file
+pub mod middlewares;
+pub mod guards;
+pub mod tokens;
+pub mod revocation;
+pub mod throttle;
@@ -0,0 +1,168 @@
+use crate::auth::tokens;
+use crate::utils::auth::Claims;
+use crate::utils::config::get_revocation_cache_ttl;
+use chrono::{DateTime, Utc};
+use entity::revoked_token::{ActiveModel, Column, Entity as RevokedToken};
+use entity::token_family::{Column as TokenFamilyColumn, Entity as TokenFamily};
+use entity::user::{Column as UserColumn, Entity as User};
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// entries are dropped once a cache grows past this many keys
+const CACHE_CAPACITY: usize = 10_000;
+
+struct TtlMap<K, V> {
+    entries: RwLock<HashMap<K, (V, Instant)>>,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlMap<K, V> {
+    fn new() -> Self {
+        TtlMap { entries: RwLock::new(HashMap::new()) }
+    }
+
+    fn get(&self, key: &K, ttl: Duration) -> Option<V> {
+        let entries = self.entries.read().unwrap();
+        entries.get(key)
+            .filter(|(_, stored_at)| stored_at.elapsed() < ttl)
+            .map(|(value, _)| value.clone())
+    }
+
+    fn insert(&self, key: K, value: V, ttl: Duration) {
+        let mut entries = self.entries.write().unwrap();
+        if entries.len() >= CACHE_CAPACITY {
+            entries.retain(|_, (_, stored_at)| stored_at.elapsed() < ttl);
+        }
+        if entries.len() >= CACHE_CAPACITY {
+            entries.clear();
+        }
+        entries.insert(key, (value, Instant::now()));
+    }
+
+    fn remove(&self, key: &K) {
+        self.entries.write().unwrap().remove(key);
+    }
+}
+
+/// What `authenticate` needs to know about the user behind a token.
+#[derive(Clone, Copy)]
+pub struct AccountState {
+    pub is_active: bool,
+    /// in microseconds since the epoch, like `Claims::issued_at_micros`
+    tokens_valid_after: Option<i64>,
+}
+
+/// Answers "has this token been revoked?" and "is its account active?" for `authenticate`.
+/// Results are kept in memory for `REVOCATION_CACHE_TTL` seconds, so a logout or deactivation
+/// handled by another process can take up to that long to be seen here; logouts handled by
+/// this process are seen immediately.
+pub struct RevocationList {
+    ttl: Duration,
+    revoked_jtis: TtlMap<Uuid, bool>,
+    accounts: TtlMap<i32, Option<AccountState>>,
+}
+
+impl RevocationList {
+    pub fn new() -> Self {
+        RevocationList {
+            ttl: get_revocation_cache_ttl(),
+            revoked_jtis: TtlMap::new(),
+            accounts: TtlMap::new(),
+        }
+    }
+
+    /// The state of the account `user_id`, or `None` when it doesn't exist.
+    pub async fn account(&self, db: &DatabaseConnection, user_id: i32) -> Result<Option<AccountState>, DbErr> {
+        if let Some(account) = self.accounts.get(&user_id, self.ttl) {
+            return Ok(account);
+        }
+
+        let account = User::find_by_id(user_id).one(db).await?.map(|user| AccountState {
+            is_active: user.is_active.unwrap_or(false),
+            tokens_valid_after: user.tokens_valid_after.map(|timestamp| timestamp.timestamp_micros()),
+        });
+        self.accounts.insert(user_id, account, self.ttl);
+        Ok(account)
+    }
+
+    pub async fn is_revoked(&self, db: &DatabaseConnection, claims: &Claims) -> Result<bool, DbErr> {
+        let valid_after = self.account(db, claims.id).await?.and_then(|account| account.tokens_valid_after);
+        if valid_after.is_some_and(|valid_after| claims.issued_at_micros() <= valid_after) {
+            return Ok(true);
+        }
+
+        match self.revoked_jtis.get(&claims.jti, self.ttl) {
+            Some(revoked) => Ok(revoked),
+            None => {
+                let revoked = RevokedToken::find_by_id(claims.jti).one(db).await?.is_some();
+                self.revoked_jtis.insert(claims.jti, revoked, self.ttl);
+                Ok(revoked)
+            }
+        }
+    }
+
+    /// Revokes the token described by `claims` along with its refresh token family.
+    pub async fn revoke(&self, db: &DatabaseConnection, claims: &Claims) -> Result<(), DbErr> {
+        let now = Utc::now().fixed_offset();
+        let expires_at = DateTime::from_timestamp(claims.exp, 0)
+            .map(|timestamp| timestamp.fixed_offset())
+            .unwrap_or(now);
+
+        let token = ActiveModel {
+            jti: Set(claims.jti),
+            user_id: Set(claims.id),
+            expires_at: Set(expires_at),
+            revoked_at: Set(now),
+        };
+        RevokedToken::insert(token)
+            .on_conflict(OnConflict::column(Column::Jti).do_nothing().to_owned())
+            .do_nothing()
+            .exec(db)
+            .await?;
+        self.revoked_jtis.insert(claims.jti, true, self.ttl);
+
+        if let Some(family) = claims.fam {
+            tokens::revoke(db, family).await?;
+        }
+
+        // expired tokens are rejected anyway, no need to remember them
+        RevokedToken::delete_many()
+            .filter(Column::ExpiresAt.lt(now))
+            .exec(db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Drops what is cached about the account `user_id`, so a change to it is seen right away.
+    pub fn forget(&self, user_id: i32) {
+        self.accounts.remove(&user_id);
+    }
+
+    /// Invalidates every token issued to `user_id` up to now.
+    pub async fn revoke_all(&self, db: &DatabaseConnection, user_id: i32) -> Result<(), DbErr> {
+        let now = Utc::now();
+
+        User::update_many()
+            .col_expr(UserColumn::TokensValidAfter, Expr::value(now.fixed_offset()))
+            .filter(UserColumn::Id.eq(user_id))
+            .exec(db)
+            .await?;
+        self.forget(user_id);
+
+        TokenFamily::update_many()
+            .col_expr(TokenFamilyColumn::RevokedAt, Expr::value(now.fixed_offset()))
+            .col_expr(TokenFamilyColumn::UpdatedAt, Expr::value(now.fixed_offset()))
+            .filter(TokenFamilyColumn::UserId.eq(user_id))
+            .filter(TokenFamilyColumn::RevokedAt.is_null())
+            .exec(db)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use crate::utils::config::{get_login_backoff_base, get_login_lockout_duration, get_login_max_failures, get_login_max_failures_per_ip};
+use chrono::{TimeDelta, Utc};
+use entity::login_throttle::{ActiveModel, Column, Entity as LoginThrottle};
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use std::time::Duration;
+
+// failures in a row that don't slow the next login down, so a typo costs nothing
+const FREE_FAILURES_PER_ACCOUNT: u32 = 3;
+const FREE_FAILURES_PER_IP: u32 = 10;
+
+/// What failed logins are counted against. Counters live in the `login_throttle` table, so
+/// every worker sees the same ones.
+pub enum ThrottleKey {
+    /// an existing account
+    User(i32),
+    /// a username or email nobody goes by, throttled like an account so a lockout doesn't tell
+    /// whether it exists
+    Name(String),
+    /// the address the logins come from, whichever accounts they try
+    Ip(String),
+}
+
+impl ThrottleKey {
+    fn key(&self) -> String {
+        match self {
+            ThrottleKey::User(id) => format!("user:{}", id),
+            ThrottleKey::Name(name) => format!("name:{}", name),
+            ThrottleKey::Ip(ip) => format!("ip:{}", ip),
+        }
+    }
+
+    fn policy(&self) -> Policy {
+        let (free, max) = match self {
+            ThrottleKey::Ip(_) => (FREE_FAILURES_PER_IP, get_login_max_failures_per_ip()),
+            _ => (FREE_FAILURES_PER_ACCOUNT, get_login_max_failures()),
+        };
+        Policy { free, max, base: get_login_backoff_base(), lockout: get_login_lockout_duration() }
+    }
+}
+
+/// How failed logins against one key are slowed down.
+struct Policy {
+    /// failures in a row that cost nothing
+    free: u32,
+    /// failures in a row that lock the key out, `0` turns throttling off
+    max: u32,
+    /// the wait after the first failure past the free ones
+    base: Duration,
+    /// the longest wait
+    lockout: Duration,
+}
+
+impl Policy {
+    /// How long logins wait after `failures` failures in a row. Past the free ones the wait
+    /// doubles with every failure, and at the maximum it becomes a lockout.
+    fn delay(&self, failures: u32) -> Duration {
+        if self.max == 0 {
+            Duration::ZERO
+        } else if failures >= self.max {
+            self.lockout
+        } else if failures < self.free {
+            Duration::ZERO
+        } else {
+            let doublings = (failures - self.free).min(31);
+            self.base.saturating_mul(1 << doublings).min(self.lockout)
+        }
+    }
+}
+
+/// Seconds until a login for `keys` may be tried again, or `None` when none of them is waiting.
+pub async fn retry_after(db: &DatabaseConnection, keys: &[ThrottleKey]) -> Result<Option<u64>, DbErr> {
+    let now = Utc::now().fixed_offset();
+    let locked_until = LoginThrottle::find()
+        .filter(Column::Key.is_in(keys.iter().map(ThrottleKey::key)))
+        .filter(Column::LockedUntil.gt(now))
+        .all(db)
+        .await?
+        .into_iter()
+        .filter_map(|throttle| throttle.locked_until)
+        .max();
+
+    Ok(locked_until.map(|locked_until| {
+        let wait = (locked_until - now).to_std().unwrap_or_default();
+        wait.as_secs() + u64::from(wait.subsec_nanos() > 0)
+    }))
+}
+
+/// Counts a failed login against each of `keys`. Failures older than the lockout duration are
+/// forgotten.
+pub async fn record_failure(db: &DatabaseConnection, keys: &[ThrottleKey]) -> Result<(), DbErr> {
+    let now = Utc::now().fixed_offset();
+    let forget_before = now - TimeDelta::from_std(get_login_lockout_duration()).unwrap_or_default();
+
+    for key in keys {
+        let throttle = ActiveModel {
+            key: Set(key.key()),
+            failures: Set(1),
+            last_failure_at: Set(now),
+            locked_until: Set(None),
+        };
+        // counted in the database, so concurrent failures on other workers add up
+        let failures = Expr::cust_with_values(
+            "CASE WHEN \"login_throttle\".\"last_failure_at\" < $1 THEN 1 ELSE \"login_throttle\".\"failures\" + 1 END",
+            [forget_before],
+        );
+        let throttle = LoginThrottle::insert(throttle)
+            .on_conflict(
+                OnConflict::column(Column::Key)
+                    .value(Column::Failures, failures)
+                    .update_column(Column::LastFailureAt)
+                    .to_owned(),
+            )
+            .exec_with_returning(db)
+            .await?;
+
+        let delay = key.policy().delay(throttle.failures.max(0) as u32);
+        if !delay.is_zero() {
+            LoginThrottle::update_many()
+                .col_expr(Column::LockedUntil, Expr::value(now + TimeDelta::from_std(delay).unwrap_or_default()))
+                .filter(Column::Key.eq(throttle.key))
+                .exec(db)
+                .await?;
+        }
+    }
+
+    LoginThrottle::delete_many()
+        .filter(Column::LastFailureAt.lt(forget_before))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Forgets the failures counted against `key`, which also lifts its lockout.
+pub async fn reset(db: &DatabaseConnection, key: &ThrottleKey) -> Result<(), DbErr> {
+    LoginThrottle::delete_many()
+        .filter(Column::Key.eq(key.key()))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(max: u32) -> Policy {
+        Policy { free: 3, max, base: Duration::from_secs(1), lockout: Duration::from_secs(900) }
+    }
+
+    #[test]
+    fn lets_the_first_failures_through() {
+        for failures in 0..3 {
+            assert_eq!(policy(10).delay(failures), Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn doubles_the_wait_with_every_failure() {
+        assert_eq!(policy(20).delay(3), Duration::from_secs(1));
+        assert_eq!(policy(20).delay(4), Duration::from_secs(2));
+        assert_eq!(policy(20).delay(5), Duration::from_secs(4));
+        // capped at the lockout before the maximum is reached
+        assert_eq!(policy(20).delay(19), Duration::from_secs(900));
+    }
+
+    #[test]
+    fn locks_out_at_the_maximum() {
+        assert_eq!(policy(10).delay(9), Duration::from_secs(64));
+        assert_eq!(policy(10).delay(10), Duration::from_secs(900));
+        assert_eq!(policy(10).delay(u32::MAX), Duration::from_secs(900));
+        // a maximum below the free failures locks out straight away
+        assert_eq!(policy(2).delay(2), Duration::from_secs(900));
+    }
+
+    #[test]
+    fn does_not_throttle_without_a_maximum() {
+        assert_eq!(policy(0).delay(0), Duration::ZERO);
+        assert_eq!(policy(0).delay(u32::MAX), Duration::ZERO);
+    }
+
+    #[test]
+    fn counts_addresses_separately_from_accounts() {
+        assert_eq!(ThrottleKey::User(1).policy().free, FREE_FAILURES_PER_ACCOUNT);
+        assert_eq!(ThrottleKey::Name("nobody".to_string()).policy().free, FREE_FAILURES_PER_ACCOUNT);
+        assert_eq!(ThrottleKey::Ip("192.0.2.1".to_string()).policy().free, FREE_FAILURES_PER_IP);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::utils::auth::{Claims, JSONWebToken, Token, REFRESH_TOKEN_EXPIRY};
+use crate::utils::config::get_jwt_keys;
+use crate::utils::errors::AppError;
+use chrono::Utc;
+use entity::token_family::{ActiveModel, Column, Entity as TokenFamily};
+use sea_orm::sea_query::Expr;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use std::fmt;
+use uuid::Uuid;
+
+/// Issues the first token pair of a new refresh token family, e.g. on login.
+pub async fn issue(db: &DatabaseConnection, id: i32, email: String) -> Result<Token, DbErr> {
+    let now = Utc::now().fixed_offset();
+    let family = Uuid::new_v4();
+    let refresh_jti = Uuid::new_v4();
+
+    ActiveModel {
+        id: Set(family),
+        user_id: Set(id),
+        current_jti: Set(refresh_jti),
+        expires_at: Set(now + REFRESH_TOKEN_EXPIRY),
+        revoked_at: Set(None),
+        created_at: Set(now),
+        updated_at: Set(now),
+    }
+        .insert(db)
+        .await?;
+
+    let jwt = JSONWebToken { keys: get_jwt_keys() };
+    Ok(jwt.encode(id, email, family, refresh_jti))
+}
+
+/// Exchanges a refresh token for a new pair. Every refresh token can be used once: the
+/// family only accepts its `current_jti`, and presenting an older one means the token was
+/// stolen or replayed, so the whole family gets revoked.
+pub async fn rotate(db: &DatabaseConnection, claims: Claims) -> Result<Token, RefreshError> {
+    let family = claims.fam.ok_or(RefreshError::UnknownFamily)?;
+    let now = Utc::now().fixed_offset();
+    let refresh_jti = Uuid::new_v4();
+
+    let result = TokenFamily::update_many()
+        .col_expr(Column::CurrentJti, Expr::value(refresh_jti))
+        .col_expr(Column::ExpiresAt, Expr::value(now + REFRESH_TOKEN_EXPIRY))
+        .col_expr(Column::UpdatedAt, Expr::value(now))
+        .filter(Column::Id.eq(family))
+        .filter(Column::UserId.eq(claims.id))
+        .filter(Column::CurrentJti.eq(claims.jti))
+        .filter(Column::RevokedAt.is_null())
+        .filter(Column::ExpiresAt.gt(now))
+        .exec(db)
+        .await?;
+
+    if result.rows_affected == 0 {
+        let family_model = TokenFamily::find_by_id(family).one(db).await?;
+        return match family_model {
+            None => Err(RefreshError::UnknownFamily),
+            Some(model) if model.revoked_at.is_some() => Err(RefreshError::Revoked),
+            Some(model) if model.current_jti == claims.jti => Err(RefreshError::Expired),
+            Some(_) => {
+                log::warn!("Refresh token reuse detected for user {}, revoking family {}", claims.id, family);
+                revoke(db, family).await?;
+                Err(RefreshError::Reused)
+            }
+        };
+    }
+
+    let jwt = JSONWebToken { keys: get_jwt_keys() };
+    Ok(jwt.encode(claims.id, claims.email, family, refresh_jti))
+}
+
+pub async fn revoke(db: &DatabaseConnection, family: Uuid) -> Result<(), DbErr> {
+    let now = Utc::now().fixed_offset();
+    TokenFamily::update_many()
+        .col_expr(Column::RevokedAt, Expr::value(now))
+        .col_expr(Column::UpdatedAt, Expr::value(now))
+        .filter(Column::Id.eq(family))
+        .filter(Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+
+#[derive(Debug)]
+pub enum RefreshError {
+    UnknownFamily,
+    Expired,
+    Revoked,
+    Reused,
+    Database(DbErr),
+}
+
+impl From<DbErr> for RefreshError {
+    fn from(err: DbErr) -> Self {
+        RefreshError::Database(err)
+    }
+}
+
+impl fmt::Display for RefreshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RefreshError::UnknownFamily => write!(f, "Unknown refresh token"),
+            RefreshError::Expired => write!(f, "Refresh token has expired"),
+            RefreshError::Revoked => write!(f, "Refresh token has been revoked"),
+            RefreshError::Reused => write!(f, "Refresh token has already been used"),
+            RefreshError::Database(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<RefreshError> for AppError {
+    fn from(err: RefreshError) -> Self {
+        match err {
+            RefreshError::Database(err) => AppError::Database(err),
+            err => AppError::Unauthorized(err.to_string()),
+        }
+    }
+}
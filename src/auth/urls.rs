@@ -6,5 +6,8 @@ pub fn routes(config: &mut web::ServiceConfig) {
         .service(
             web::scope("/auth")
                 .service(handlers::refresh_jwt)
-        );
+                .service(handlers::logout)
+                .service(handlers::logout_all)
+        )
+        .service(handlers::jwks);
 }
\ No newline at end of file
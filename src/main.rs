@@ -6,10 +6,12 @@ mod auth;
 use actix_web::middleware::Logger;
 use actix_web::{web, App, HttpServer};
 use migration::{Migrator, MigratorTrait};
-use utils::config::{get_address, get_db_connection};
+use utils::config::{get_address, get_db_connection, get_mailer};
 use utils::log::set_logger;
 
+use crate::auth::revocation::RevocationList;
 use crate::utils::app_state::AppState;
+use crate::utils::errors::AppError;
 
 fn init() {
     set_logger();
@@ -26,9 +28,16 @@ async fn main() -> std::io::Result<()> {
     let (host, port) = get_address();
     log::info!("Server running at http://{}:{}", host, port);
 
+    // shared by all workers, so the revocation cache is too
+    let app_state = web::Data::new(AppState { db, revocations: RevocationList::new(), mailer: get_mailer() });
+
     HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(AppState { db: db.clone() }))
+            .app_data(app_state.clone())
+            // report malformed paths, queries and bodies as problem details too
+            .app_data(web::PathConfig::default().error_handler(|err, _| AppError::BadRequest(err.to_string()).into()))
+            .app_data(web::QueryConfig::default().error_handler(|err, _| AppError::BadRequest(err.to_string()).into()))
+            .app_data(web::JsonConfig::default().error_handler(|err, _| AppError::BadRequest(err.to_string()).into()))
             .wrap(Logger::default())
             .configure(home::urls::routes)
             .configure(users::urls::routes)
@@ -0,0 +1,53 @@
+use crate::utils::auth::{JSONWebToken, EMAIL_VERIFICATION_EXPIRY};
+use crate::utils::config::{get_email_verification_url, get_jwt_keys};
+use crate::utils::mail::{link, Email};
+use entity::user::Model as UserModel;
+
+/// The email with a signed link that activates `user`. The link only works for the email
+/// address it was sent to, and stops working when the user's tokens are revoked, e.g. because
+/// an admin deactivated them.
+pub fn email(user: &UserModel) -> Email {
+    let address = user.email.clone().unwrap_or_default();
+    let link = signed_link(user, &address);
+
+    Email {
+        to: address,
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Hi {},\n\n\
+            please open this link within {} hours to verify your email address and activate your \
+            account:\n\n{}\n\n\
+            If you didn't expect this email, you can ignore it.\n",
+            user.username.as_deref().unwrap_or_default(),
+            EMAIL_VERIFICATION_EXPIRY.num_hours(),
+            link,
+        ),
+    }
+}
+
+/// The email with a signed link that makes the pending address of `user` their email address.
+/// Until the link is opened, the current address stays in use.
+pub fn change_email(user: &UserModel) -> Email {
+    let address = user.pending_email.clone().unwrap_or_default();
+    let link = signed_link(user, &address);
+
+    Email {
+        to: address,
+        subject: "Confirm your new email address".to_string(),
+        body: format!(
+            "Hi {},\n\n\
+            please open this link within {} hours to confirm that this is your new email \
+            address:\n\n{}\n\n\
+            If you didn't expect this email, you can ignore it and your address stays as it is.\n",
+            user.username.as_deref().unwrap_or_default(),
+            EMAIL_VERIFICATION_EXPIRY.num_hours(),
+            link,
+        ),
+    }
+}
+
+fn signed_link(user: &UserModel, address: &str) -> String {
+    let jwt = JSONWebToken { keys: get_jwt_keys() };
+    let token = jwt.encode_email_verification(user.id, address.to_string());
+    link(&get_email_verification_url(), &token)
+}
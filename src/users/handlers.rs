@@ -1,244 +1,1155 @@
-use crate::users::models::{LoginResponse, UserRequest};
-use crate::users::pagination::{Pagination, PaginationQuery};
+use crate::auth::guards::CurrentUser;
+use crate::auth::middlewares::authenticate;
+use crate::auth::throttle::{self, ThrottleKey};
+use crate::auth::tokens;
+use crate::users::email_verification;
+use crate::users::invitations::{self, Role, INVITATION_FIELDS};
+use crate::users::logins::{self, LoginOutcome, LOGIN_FIELDS};
+use crate::users::mfa;
+use crate::users::models::{AcceptInvitation, CreateInvitation, CreateUser, DeleteQuery, ForgotPassword, InvitationResponse, LoginEventResponse, LoginRequest, LoginResponse, MfaChallenge, MfaCode, MfaLoginResponse, MfaSetupResponse, MfaTokenRequest, PatchUser, RecoveryCodesResponse, RegisterUser, ReplaceUser, ResetPassword, SearchResult, UserResponse, UserView, VerifyEmailQuery, VerifyMfa};
+use crate::users::password_reset;
+use crate::users::search::{search, SearchHit};
 use crate::users::serializers::UserSerializer;
 use crate::utils::app_state::AppState;
-use crate::utils::auth::JSONWebToken;
-use crate::utils::config::get_secret;
+use crate::utils::auth::{Claims, JSONWebToken, TokenType};
+use crate::utils::config::{get_email_verification_required, get_jwt_keys, get_registration_mode, RegistrationMode};
+use crate::utils::cursor::CursorPagination;
+use crate::utils::errors::AppError;
+use crate::utils::etag::{check_if_match, etag, is_not_modified, write_conflict};
+use crate::utils::fields::{parse_include, Fields, FieldsQuery};
+use crate::utils::filter::{self, Filters, SearchQuery};
+use crate::utils::mail::Email;
+use crate::utils::pagination::{Pagination, PaginationQuery};
+use crate::utils::password::PasswordHasher;
+use crate::utils::patch::PatchBody;
 use crate::utils::response::ApiResponse;
+use crate::utils::sort::Sort;
+use crate::utils::validation::{parse_valid, ValidatedJson};
 
-use actix_web::web::{Data, Json, Path, Query};
-use actix_web::{delete, get, patch, post, put, Error, HttpResponse, Responder};
-use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use actix_web::web::{Data, Path, Query};
+use actix_web::http::header::ETag;
+use actix_web::middleware::from_fn;
+use actix_web::{delete, get, patch, post, put, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use entity::invitation;
+use entity::invitation::Entity as Invitation;
+use entity::login_event;
+use entity::login_event::Entity as LoginEvent;
+use entity::user::ActiveModel;
 use entity::user::Column;
 use entity::user::Entity as User;
+use entity::user::Model;
+use sea_orm::sea_query::{Expr, Func};
 use sea_orm::ActiveValue::Set;
-use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, PaginatorTrait, QueryFilter};
+use sea_orm::{ActiveModelBehavior, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, IntoActiveModel, PaginatorTrait, QueryFilter, TransactionTrait};
+use serde_json::{json, Value as JsonValue};
+use std::collections::BTreeMap;
+
+/// Related resources `include=` can embed in a user. There are none yet.
+const INCLUDES: &[&str] = &[];
+
+const PUBLIC_SEARCH: &[Column] = &[Column::Username, Column::Firstname, Column::Lastname];
+const ADMIN_SEARCH: &[Column] = &[Column::Username, Column::Firstname, Column::Lastname, Column::Email];
 
 
 #[post("/create")]
-pub async fn create_user(payload: Json<UserRequest>, app_state: Data<AppState>) -> Result<impl Responder, Error> {
-    let serializer = UserSerializer { data: payload };
-    let user = serializer.serialize();
-
-    let result = user.insert(&app_state.db).await;
-    match result {
-        Ok(user) => Ok(HttpResponse::Ok().json(user)),
-        Err(err) => {
-            let response = ApiResponse { message: err.to_string() };
-            Ok(HttpResponse::BadRequest().json(response))
-        }
+pub async fn create_user(payload: ValidatedJson<CreateUser>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    current_user.require_admin()?;
+    if payload.is_admin.unwrap_or(false) || payload.is_superadmin.unwrap_or(false) {
+        current_user.require_superadmin()?;
     }
+
+    let serializer = UserSerializer { data: payload.into_inner() };
+    let user = serializer.serialize()?.insert(&app_state.db).await?;
+
+    if !user.is_active.unwrap_or(false) {
+        send_verification_email(&app_state, user.id, email_verification::email(&user));
+    }
+
+    Ok(HttpResponse::Ok()
+        .insert_header(ETag(etag(user.version)))
+        .json(UserResponse::new(user, UserView::Admin)))
 }
 
-#[post("/login")]
-pub async fn login(payload: Json<UserRequest>, app_state: Data<AppState>) -> Result<impl Responder, Error> {
-    let result = User::find()
-        .filter(Column::Username.eq(&payload.username.clone().unwrap()))
-        .filter(Column::Password.eq(&payload.password.clone().unwrap()))
+/// Lets anyone sign up when `REGISTRATION_MODE` is `open`. Unless email verification is turned
+/// off, the account starts inactive and gets a verification link; otherwise the new user is
+/// logged in right away.
+#[post("/auth/register")]
+pub async fn register(payload: ValidatedJson<RegisterUser>, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    match get_registration_mode() {
+        RegistrationMode::Open => {}
+        RegistrationMode::InviteOnly => return Err(AppError::Forbidden("Registration is by invitation only".to_string())),
+        RegistrationMode::Closed => return Err(AppError::Forbidden("Registration is closed".to_string())),
+    }
+
+    let verify = get_email_verification_required();
+    let serializer = UserSerializer { data: payload.into_inner().into_create_user(!verify) };
+    let user = serializer.serialize()?.insert(&app_state.db).await?;
+
+    if verify {
+        send_verification_email(&app_state, user.id, email_verification::email(&user));
+        let response = ApiResponse { message: "Registered, check your email to verify your address and activate the account".to_string() };
+        return Ok(HttpResponse::Created().json(response));
+    }
+
+    let tokens = tokens::issue(&app_state.db, user.id, user.email.unwrap_or_default()).await?;
+    let response = LoginResponse {
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
+    };
+    Ok(HttpResponse::Created().json(response))
+}
+
+#[post("/users/login")]
+pub async fn login(request: HttpRequest, payload: ValidatedJson<LoginRequest>, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let LoginRequest { identifier, password } = payload.into_inner();
+    let normalized = identifier.to_lowercase();
+    // usernames can't contain `@`, so anything with one is an email
+    let column = if normalized.contains('@') { Column::Email } else { Column::Username };
+    let user_option = User::find_existing()
+        .filter(Expr::expr(Func::lower(Expr::col(column))).eq(&normalized))
         .one(&app_state.db)
-        .await;
-
-    match result {
-        Ok(user_option) => {
-            match user_option {
-                None => {
-                    let response = ApiResponse { message: format!("User '{}' not found", &payload.username.clone().unwrap()) };
-                    Ok(HttpResponse::NotFound().json(response))
-                }
-                Some(user) => {
-                    let jwt = JSONWebToken { secret: get_secret() };
-                    let tokens = jwt.encode(user.id, user.email.unwrap());
-                    let response = LoginResponse {
-                        token: tokens.token,
-                        refresh_token: tokens.refresh_token,
-                    };
-                    Ok(HttpResponse::Ok().json(response))
+        .await?;
+
+    let mut throttle_keys = vec![match &user_option {
+        Some(user) => ThrottleKey::User(user.id),
+        None => ThrottleKey::Name(normalized),
+    }];
+    throttle_keys.extend(logins::client_ip(&request).map(ThrottleKey::Ip));
+    if let Some(seconds) = throttle::retry_after(&app_state.db, &throttle_keys).await? {
+        logins::record(&app_state.db, &request, user_option.map(|user| user.id), &identifier, LoginOutcome::Locked).await;
+        return Err(AppError::TooManyRequests(seconds));
+    }
+
+    let hasher = PasswordHasher::new();
+    let verified = match &user_option {
+        Some(user) => hasher.verify(&password, user.password.as_deref().unwrap_or_default())?,
+        None => {
+            hasher.dummy_verify(&password);
+            false
+        }
+    };
+
+    let user = match user_option {
+        Some(user) if verified => user,
+        user_option => {
+            let outcome = if user_option.is_some() { LoginOutcome::WrongPassword } else { LoginOutcome::UnknownUser };
+            throttle::record_failure(&app_state.db, &throttle_keys).await?;
+            logins::record(&app_state.db, &request, user_option.map(|user| user.id), &identifier, outcome).await;
+            // the same answer either way, so it doesn't tell which usernames exist
+            return Err(AppError::Unauthorized("Invalid credentials".to_string()));
+        }
+    };
+
+    if !user.is_active.unwrap_or(false) {
+        logins::record(&app_state.db, &request, Some(user.id), &identifier, LoginOutcome::Inactive).await;
+        return Err(AppError::InactiveUser);
+    }
+
+    // move the stored hash to the current parameters while we still have the password
+    if hasher.needs_rehash(user.password.as_deref().unwrap_or_default()) {
+        match hasher.hash(&password) {
+            Ok(hash) => {
+                let mut active_user = user.clone().into_active_model();
+                active_user.password = Set(Some(hash));
+                if let Err(err) = active_user.update(&app_state.db).await {
+                    log::error!("Error rehashing password for user {}: {}", user.id, err);
                 }
             }
-        }
-        Err(err) => {
-            let response = ApiResponse { message: err.to_string() };
-            Ok(HttpResponse::InternalServerError().json(response))
+            Err(err) => log::error!("Error rehashing password for user {}: {}", user.id, err),
         }
     }
+
+    // failures still count until the second factor is right too
+    if user.mfa_enabled_at.is_some() || user.mfa_required.unwrap_or(false) {
+        logins::record(&app_state.db, &request, Some(user.id), &identifier, LoginOutcome::MfaPending).await;
+        let jwt = JSONWebToken { keys: get_jwt_keys() };
+        let response = MfaChallenge {
+            mfa_token: jwt.encode_mfa_pending(user.id, user.email.clone().unwrap_or_default()),
+            enrollment_required: user.mfa_enabled_at.is_none(),
+        };
+        return Ok(HttpResponse::Ok().json(response));
+    }
+
+    let response = complete_login(&request, &app_state, &user, &identifier).await?;
+    Ok(HttpResponse::Ok().json(response))
 }
 
+/// The second step of logging in with two-factor authentication. Exchanges the `mfa_token`
+/// from `login` and a code for a token pair. The first code after `enroll_mfa` turns
+/// two-factor authentication on, and the answer then has the recovery codes too.
+#[post("/verify")]
+pub async fn verify_mfa(request: HttpRequest, payload: ValidatedJson<VerifyMfa>, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let VerifyMfa { mfa_token, code } = payload.into_inner();
+    let (claims, user) = mfa_login(&app_state, mfa_token).await?;
+    let identifier = user.username.clone().unwrap_or_default();
 
-#[get("")]
-pub async fn get_users(query: Query<PaginationQuery>, app_state: Data<AppState>) -> Result<impl Responder, Error> {
-    let page = Pagination { query: query.clone() };
-    let user_pages = page.paginate();
-    let total = user_pages.clone().count(&app_state.db).await.unwrap_or_else(|_| 0);
-
-    let pages = user_pages.all(&app_state.db).await;
-    match pages {
-        Ok(users) => {
-            let result = page.response(users, total);
-            Ok(HttpResponse::Ok().json(result))
-        }
-        Err(err) => {
-            let response = ApiResponse { message: err.to_string() };
-            Ok(HttpResponse::BadRequest().json(response))
-        }
+    if !verify_second_factor(&request, &app_state, &user, &code).await? {
+        logins::record(&app_state.db, &request, Some(user.id), &identifier, LoginOutcome::WrongMfaCode).await;
+        return Err(AppError::Unauthorized("Invalid code".to_string()));
     }
+    // a pending login is only completed once
+    app_state.revocations.revoke(&app_state.db, &claims).await?;
+
+    let recovery_codes = match user.mfa_enabled_at {
+        Some(_) => None,
+        None => Some(enable_mfa_with_codes(&app_state, user.id).await?),
+    };
+    let tokens = complete_login(&request, &app_state, &user, &identifier).await?;
+    Ok(HttpResponse::Ok().json(MfaLoginResponse { tokens, recovery_codes }))
 }
 
-#[get("/{id}")]
-pub async fn get_user(id: Path<i32>, app_state: Data<AppState>) -> Result<impl Responder, Error> {
-    let user_id = id.into_inner();
-    let result = User::find_by_id(user_id.clone())
+/// Starts setting up two-factor authentication during login, for users it is required for
+/// who haven't set it up yet. The first code goes to `verify_mfa`.
+#[post("/enroll")]
+pub async fn enroll_mfa(payload: ValidatedJson<MfaTokenRequest>, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let (_, user) = mfa_login(&app_state, payload.into_inner().mfa_token).await?;
+    Ok(HttpResponse::Ok().json(start_mfa_setup(&app_state, &user).await?))
+}
+
+/// Starts setting up two-factor authentication for the current user. It is turned on by
+/// `enable_mfa` with the first code.
+#[post("/setup", wrap = "from_fn(authenticate)")]
+pub async fn setup_mfa(current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let user = find_user(&app_state.db, current_user.id).await?;
+    Ok(HttpResponse::Ok().json(start_mfa_setup(&app_state, &user).await?))
+}
+
+/// Turns on two-factor authentication with a code for the secret from `setup_mfa`, and
+/// answers with the recovery codes.
+#[post("/enable", wrap = "from_fn(authenticate)")]
+pub async fn enable_mfa(request: HttpRequest, payload: ValidatedJson<MfaCode>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let user = find_user(&app_state.db, current_user.id).await?;
+    if user.mfa_enabled_at.is_some() {
+        return Err(AppError::Conflict("Two-factor authentication is already enabled".to_string()));
+    }
+    if !verify_second_factor(&request, &app_state, &user, &payload.code).await? {
+        return Err(invalid_code());
+    }
+
+    let recovery_codes = enable_mfa_with_codes(&app_state, user.id).await?;
+    Ok(HttpResponse::Ok().json(RecoveryCodesResponse { recovery_codes }))
+}
+
+/// Turns off two-factor authentication, unless an admin requires it for the account.
+#[post("/disable", wrap = "from_fn(authenticate)")]
+pub async fn disable_mfa(request: HttpRequest, payload: ValidatedJson<MfaCode>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let user = find_user(&app_state.db, current_user.id).await?;
+    if user.mfa_required.unwrap_or(false) {
+        return Err(AppError::Forbidden("Two-factor authentication is required for this account".to_string()));
+    }
+    if user.mfa_enabled_at.is_none() {
+        return Err(AppError::Conflict("Two-factor authentication is not enabled".to_string()));
+    }
+    if !verify_second_factor(&request, &app_state, &user, &payload.code).await? {
+        return Err(invalid_code());
+    }
+
+    let txn = app_state.db.begin().await?;
+    mfa::disable(&txn, user.id).await?;
+    txn.commit().await?;
+
+    let response = ApiResponse { message: "Two-factor authentication has been disabled".to_string() };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Replaces the recovery codes of the current user, which voids the old ones.
+#[post("/recovery-codes", wrap = "from_fn(authenticate)")]
+pub async fn regenerate_recovery_codes(request: HttpRequest, payload: ValidatedJson<MfaCode>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let user = find_user(&app_state.db, current_user.id).await?;
+    if user.mfa_enabled_at.is_none() {
+        return Err(AppError::Conflict("Two-factor authentication is not enabled".to_string()));
+    }
+    if !verify_second_factor(&request, &app_state, &user, &payload.code).await? {
+        return Err(invalid_code());
+    }
+
+    // the old codes are only gone once the new ones are stored
+    let txn = app_state.db.begin().await?;
+    let recovery_codes = mfa::issue_recovery_codes(&txn, user.id).await?;
+    txn.commit().await?;
+    Ok(HttpResponse::Ok().json(RecoveryCodesResponse { recovery_codes }))
+}
+
+/// Emails a password reset link. The answer is the same whether or not the account exists.
+#[post("/forgot")]
+pub async fn forgot_password(payload: ValidatedJson<ForgotPassword>, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let email = payload.into_inner().email.to_lowercase();
+    let user = User::find_existing()
+        .filter(Expr::expr(Func::lower(Expr::col(Column::Email))).eq(&email))
         .one(&app_state.db)
-        .await;
-
-    match result {
-        Ok(model) => {
-            match model {
-                None => {
-                    let message = format!("User with ID `{}`, does not exist", user_id);
-                    let response = ApiResponse { message };
-                    Ok(HttpResponse::NotFound().json(response))
+        .await?;
+
+    if let Some(user) = user {
+        if let Some(token) = password_reset::issue(&app_state.db, user.id).await? {
+            let email = password_reset::email(&user, &token);
+            let app_state = app_state.clone();
+            // sent in the background, so how long the answer takes doesn't tell either
+            actix_web::rt::spawn(async move {
+                if let Err(err) = app_state.mailer.send(email).await {
+                    log::error!("Error sending password reset email to user {}: {}", user.id, err);
                 }
-                Some(user) => Ok(HttpResponse::Ok().json(user))
-            }
+            });
         }
-        Err(err) => {
-            let response = ApiResponse { message: err.to_string() };
-            Ok(HttpResponse::BadRequest().json(response))
+    }
+
+    let response = ApiResponse { message: "If an account uses this email, a password reset link has been sent to it".to_string() };
+    Ok(HttpResponse::Accepted().json(response))
+}
+
+/// Sets a new password with a token from `forgot_password` and signs the user out everywhere.
+#[post("/reset")]
+pub async fn reset_password(payload: ValidatedJson<ResetPassword>, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let ResetPassword { token, password } = payload.into_inner();
+    let hash = PasswordHasher::new().hash(&password)?;
+    let invalid_token = || AppError::BadRequest("The reset token is invalid or has expired".to_string());
+
+    let user_id = password_reset::consume(&app_state.db, &token).await?.ok_or_else(invalid_token)?;
+    let updated = User::update_many()
+        .col_expr(Column::Password, Expr::value(hash))
+        .col_expr(Column::UpdatedAt, Expr::value(Utc::now().fixed_offset()))
+        .col_expr(Column::Version, Expr::col(Column::Version).add(1))
+        .filter(Column::Id.eq(user_id))
+        .filter(Column::DeletedAt.is_null())
+        .exec(&app_state.db)
+        .await?;
+    if updated.rows_affected == 0 {
+        return Err(invalid_token());
+    }
+
+    app_state.revocations.revoke_all(&app_state.db, user_id).await?;
+    // whoever can read the user's email may log in again right away
+    throttle::reset(&app_state.db, &ThrottleKey::User(user_id)).await?;
+
+    let response = ApiResponse { message: "Password has been reset".to_string() };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Activates the account a link from `email_verification` was sent for, or makes the address
+/// a user changed to their email address.
+#[get("/auth/verify-email")]
+pub async fn verify_email(query: Query<VerifyEmailQuery>, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let invalid_link = || AppError::BadRequest("The verification link is invalid or has expired".to_string());
+    let jwt = JSONWebToken { keys: get_jwt_keys() };
+    let claims = jwt.decode(query.token.clone(), TokenType::EmailVerification).map_err(|_| invalid_link())?.claims;
+
+    let user = User::find_existing()
+        .filter(Column::Id.eq(claims.id))
+        .one(&app_state.db)
+        .await?
+        .ok_or_else(invalid_link)?;
+    if app_state.revocations.is_revoked(&app_state.db, &claims).await? {
+        return Err(invalid_link());
+    }
+    if user.pending_email.as_deref() == Some(claims.email.as_str()) {
+        let (user_id, version) = (user.id, user.version);
+        let mut user = user.into_active_model();
+        user.email = Set(Some(claims.email));
+        user.pending_email = Set(None);
+        // someone else may have taken the address in the meantime, which is a conflict
+        save_versioned(&app_state.db, user, user_id, version)
+            .await?
+            .ok_or(AppError::Conflict("The account was changed in the meantime, try again".to_string()))?;
+
+        let response = ApiResponse { message: "Email address verified, it is now the address of the account".to_string() };
+        return Ok(HttpResponse::Ok().json(response));
+    }
+    if user.email.as_deref() != Some(claims.email.as_str()) {
+        return Err(invalid_link());
+    }
+    if user.is_active.unwrap_or(false) {
+        let response = ApiResponse { message: "Email address is already verified".to_string() };
+        return Ok(HttpResponse::Ok().json(response));
+    }
+
+    let (user_id, version) = (user.id, user.version);
+    let mut user = user.into_active_model();
+    user.is_active = Set(Some(true));
+    save_versioned(&app_state.db, user, user_id, version)
+        .await?
+        .ok_or(AppError::Conflict("The account was changed in the meantime, try again".to_string()))?;
+    app_state.revocations.forget(user_id);
+
+    let response = ApiResponse { message: "Email address verified, the account is active".to_string() };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Invites someone by email. Inviting admins takes a superadmin. Earlier invitations to the
+/// same address stop working, so if an email gets lost, inviting again sends a fresh link.
+#[post("")]
+pub async fn create_invitation(payload: ValidatedJson<CreateInvitation>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    current_user.require_admin()?;
+    let CreateInvitation { email, role } = payload.into_inner();
+    if role.is_admin() {
+        current_user.require_superadmin()?;
+    }
+
+    let existing = User::find_existing()
+        .filter(Expr::expr(Func::lower(Expr::col(Column::Email))).eq(email.to_lowercase()))
+        .one(&app_state.db)
+        .await?;
+    if existing.is_some() {
+        return Err(AppError::Conflict(format!("A user with the email address `{}` already exists", email)));
+    }
+
+    let (invitation, token) = invitations::issue(&app_state.db, &email, role, current_user.id).await?;
+    let email = invitations::email(&invitation, &token);
+    let mail_state = app_state.clone();
+    let invitation_id = invitation.id;
+    // sent in the background like the other emails; a failed one is logged and can be resent
+    // by inviting again
+    actix_web::rt::spawn(async move {
+        if let Err(err) = mail_state.mailer.send(email).await {
+            log::error!("Error sending invitation {}: {}", invitation_id, err);
         }
+    });
+
+    Ok(HttpResponse::Created().json(InvitationResponse::from(invitation)))
+}
+
+/// All invitations, newest first unless `sort` says otherwise.
+#[get("")]
+pub async fn get_invitations(request: HttpRequest, query: Query<PaginationQuery>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    current_user.require_admin()?;
+
+    let sort = Sort::parse(query.sort().or(Some("-created_at")), INVITATION_FIELDS, ("id", invitation::Column::Id))?;
+    let filters = Filters::from_request(&request, INVITATION_FIELDS)?;
+    let select = filters.apply(Invitation::find());
+
+    if let Some(cursor) = CursorPagination::from_query(&query, sort.clone())? {
+        let page = cursor.fetch(select, &app_state.db).await?.map(InvitationResponse::from);
+        return Ok(page.response(&request, "invitations"));
+    }
+
+    let pagination = Pagination::from_query(&query)?;
+    let total = select.clone().count(&app_state.db).await?;
+    let invitations: Vec<InvitationResponse> = pagination.paginate(sort.apply(select, false))
+        .all(&app_state.db)
+        .await?
+        .into_iter()
+        .map(InvitationResponse::from)
+        .collect();
+
+    Ok(pagination.response(&request, "invitations", invitations, total))
+}
+
+/// Voids the link of an invitation that hasn't been accepted yet.
+#[delete("/{id}")]
+pub async fn revoke_invitation(id: Path<i32>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let invitation_id = id.into_inner();
+    current_user.require_admin()?;
+    let invitation = Invitation::find_by_id(invitation_id)
+        .one(&app_state.db)
+        .await?
+        .ok_or(AppError::NotFound(format!("Invitation with ID `{}`, does not exist", invitation_id)))?;
+    if Role::parse(&invitation.role).is_admin() {
+        current_user.require_superadmin()?;
+    }
+    if invitation.accepted_at.is_some() {
+        return Err(AppError::Conflict(format!("Invitation with ID `{}` has already been accepted", invitation_id)));
+    }
+
+    Invitation::update_many()
+        .col_expr(invitation::Column::RevokedAt, Expr::value(Utc::now().fixed_offset()))
+        .filter(invitation::Column::Id.eq(invitation_id))
+        .filter(invitation::Column::AcceptedAt.is_null())
+        .filter(invitation::Column::RevokedAt.is_null())
+        .exec(&app_state.db)
+        .await?;
+
+    let response = ApiResponse { message: format!("Revoked invitation with Id {}", invitation_id) };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Creates the account an invitation was sent for, with the role it was sent with, and logs
+/// the new user in. Works in every registration mode.
+#[post("/auth/invitations/accept")]
+pub async fn accept_invitation(payload: ValidatedJson<AcceptInvitation>, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let invalid_token = || AppError::BadRequest("The invitation is invalid or has expired".to_string());
+    let payload = payload.into_inner();
+
+    // the user is only created along with accepting, so a taken username leaves the invitation usable
+    let txn = app_state.db.begin().await?;
+    let invitation = invitations::find_pending(&txn, &payload.token).await?.ok_or_else(invalid_token)?;
+    let role = Role::parse(&invitation.role);
+    let serializer = UserSerializer { data: payload.into_create_user(invitation.email.clone(), role) };
+    let user = serializer.serialize()?.insert(&txn).await?;
+    if !invitations::accept(&txn, invitation.id, user.id).await? {
+        return Err(invalid_token());
+    }
+    txn.commit().await?;
+
+    let tokens = tokens::issue(&app_state.db, user.id, user.email.unwrap_or_default()).await?;
+    let response = LoginResponse {
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
+    };
+    Ok(HttpResponse::Created().json(response))
+}
+
+#[get("")]
+pub async fn get_users(
+    request: HttpRequest,
+    query: Query<PaginationQuery>,
+    search_query: Query<SearchQuery>,
+    fields_query: Query<FieldsQuery>,
+    current_user: Option<CurrentUser>,
+    app_state: Data<AppState>,
+) -> Result<impl Responder, AppError> {
+    let is_admin = current_user.as_ref().is_some_and(|user| user.require_admin().is_ok());
+    // owners see more of their own row, but a list only offers what every row shows
+    let (view, search_columns) = if is_admin { (UserView::Admin, ADMIN_SEARCH) } else { (UserView::Public, PUBLIC_SEARCH) };
+
+    let sort = Sort::parse(query.sort(), view.fields(), ("id", Column::Id))?;
+    let filters = Filters::from_request(&request, view.fields())?;
+    let fields = Fields::parse(fields_query.fields.as_deref(), view.fields())?;
+    parse_include(fields_query.include.as_deref(), INCLUDES)?;
+
+    let mut select = filters.apply(User::find_existing());
+    if let Some(q) = search_query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        select = select.filter(filter::search(q, search_columns));
+    }
+    let render = |user: Model| render_user(user, current_user.as_ref(), fields.as_ref());
+
+    if let Some(cursor) = CursorPagination::from_query(&query, sort.clone())? {
+        let select = match &fields {
+            Some(fields) => {
+                let required: Vec<Column> = sort.keys().iter().map(|key| key.column).chain([Column::Version]).collect();
+                fields.apply(select, &required)
+            }
+            None => select,
+        };
+        let page = cursor.fetch(select, &app_state.db).await?.map(render);
+        return Ok(page.response(&request, "users"));
+    }
+
+    let pagination = Pagination::from_query(&query)?;
+    let total = select.clone().count(&app_state.db).await?;
+    let select = match &fields {
+        Some(fields) => fields.apply(select, &[Column::Id, Column::Version]),
+        None => select,
+    };
+
+    let users: Vec<JsonValue> = pagination.paginate(sort.apply(select, false))
+        .all(&app_state.db)
+        .await?
+        .into_iter()
+        .map(render)
+        .collect();
+
+    Ok(pagination.response(&request, "users", users, total))
+}
+
+#[get("/search")]
+pub async fn search_users(
+    request: HttpRequest,
+    query: Query<PaginationQuery>,
+    search_query: Query<SearchQuery>,
+    current_user: CurrentUser,
+    app_state: Data<AppState>,
+) -> Result<impl Responder, AppError> {
+    current_user.require_admin()?;
+    let select = search(search_query.q.as_deref().unwrap_or_default()).ok_or_else(|| {
+        AppError::Validation(BTreeMap::from([("q".to_string(), vec!["must contain a letter or digit".to_string()])]))
+    })?;
+
+    let pagination = Pagination::from_query(&query)?;
+    let total = select.clone().count(&app_state.db).await?;
+
+    let results: Vec<SearchResult> = pagination.paginate(select)
+        .into_model::<SearchHit>()
+        .all(&app_state.db)
+        .await?
+        .into_iter()
+        .map(|hit| SearchResult::for_viewer(hit, Some(&current_user)))
+        .collect();
+
+    Ok(pagination.response(&request, "results", results, total))
+}
+
+#[get("/{id}")]
+pub async fn get_user(request: HttpRequest, id: Path<i32>, fields_query: Query<FieldsQuery>, current_user: Option<CurrentUser>, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let user_id = id.into_inner();
+    let view = UserView::for_viewer(current_user.as_ref(), user_id);
+    let fields = Fields::parse(fields_query.fields.as_deref(), view.fields())?;
+    parse_include(fields_query.include.as_deref(), INCLUDES)?;
+
+    let user = match &fields {
+        Some(fields) => fields
+            .apply(User::find_existing().filter(Column::Id.eq(user_id)), &[Column::Id, Column::Version])
+            .one(&app_state.db)
+            .await?
+            .ok_or(AppError::NotFound(format!("User with ID `{}`, does not exist", user_id)))?,
+        None => find_user(&app_state.db, user_id).await?,
+    };
+
+    let tag = etag(user.version);
+    if is_not_modified(&request, &tag) {
+        return Ok(HttpResponse::NotModified().insert_header(ETag(tag)).finish());
     }
+    Ok(HttpResponse::Ok()
+        .insert_header(ETag(tag))
+        .json(render_user(user, current_user.as_ref(), fields.as_ref())))
 }
 
 #[patch("/{id}")]
-pub async fn update_user(id: Path<i32>, payload: Json<UserRequest>, app_state: Data<AppState>) -> Result<impl Responder, Error> {
+pub async fn update_user(request: HttpRequest, id: Path<i32>, payload: PatchBody<PatchUser>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
     let user_id = id.into_inner();
-    let result = User::find_by_id(user_id.clone()).one(&app_state.db).await;
-
-    match result {
-        Ok(model) => {
-            match model {
-                None => {
-                    let message = format!("User with ID `{}`, does not exist", user_id.clone());
-                    let response = ApiResponse { message };
-                    Ok(HttpResponse::NotFound().json(response))
-                }
-                Some(user_model) => {
-                    let mut user = user_model.into_active_model();
-                    user.username = Set(payload.username.clone().or(user.username.unwrap()));
-                    user.firstname = Set(payload.firstname.clone().or(user.firstname.unwrap()));
-                    user.lastname = Set(payload.lastname.clone().or(user.lastname.unwrap()));
-                    user.email = Set(payload.email.clone().or(user.email.unwrap()));
-                    user.password = Set(payload.password.clone().or(user.password.unwrap()));
-                    user.is_active = Set(Option::from(payload.is_active).or(user.is_active.unwrap()));
-                    user.is_admin = Set(Option::from(payload.is_admin).or(user.is_admin.unwrap()));
-                    user.is_superadmin = Set(Option::from(payload.is_superadmin).or(user.is_superadmin.unwrap()));
-                    user.updated_at = Set(Option::from(
-                        NaiveDateTime::new(
-                            NaiveDate::from(Utc::now().naive_utc()),
-                            NaiveTime::from(Utc::now().time()),
-                        )
-                    ));
-
-                    let result = user.update(&app_state.db).await;
-                    match result {
-                        Ok(response) => Ok(HttpResponse::Ok().json(response)),
-                        Err(err) => {
-                            let response = ApiResponse { message: err.to_string() };
-                            Ok(HttpResponse::BadRequest().json(response))
-                        }
-                    }
-                }
-            }
-        }
-        Err(err) => {
-            let response = ApiResponse { message: err.to_string() };
-            Ok(HttpResponse::BadRequest().json(response))
+    current_user.require_self_or_admin(user_id)?;
+    let user_model = find_user(&app_state.db, user_id).await?;
+    check_if_match(&request, &etag(user_model.version))?;
+
+    let (version, was_active) = (user_model.version, user_model.is_active.unwrap_or(false));
+    let user = match payload {
+        PatchBody::Partial(payload) => apply_partial(&current_user, user_model, &payload)?,
+        PatchBody::Document(patch) => {
+            let document = serde_json::to_vec(&patch.apply(json!(ReplaceUser::from_model(&user_model)))?)
+                .map_err(|err| AppError::Internal(err.to_string()))?;
+            let replacement = parse_valid::<ReplaceUser>(&document)?;
+            apply_replacement(&current_user, user_model, &replacement)?
         }
+    };
+
+    let email_change = matches!(user.pending_email, Set(Some(_)));
+
+    let user = save_versioned(&app_state.db, user, user_id, version)
+        .await?
+        .ok_or_else(|| write_conflict(&request))?;
+    apply_activation(&app_state, was_active, &user).await?;
+    if email_change {
+        send_verification_email(&app_state, user.id, email_verification::change_email(&user));
     }
+    Ok(HttpResponse::Ok()
+        .insert_header(ETag(etag(user.version)))
+        .json(UserResponse::for_viewer(user, Some(&current_user))))
 }
 
 #[put("/{id}")]
-pub async fn update_user_full(id: Path<i32>, payload: Json<UserRequest>, app_state: Data<AppState>) -> Result<impl Responder, Error> {
+pub async fn update_user_full(request: HttpRequest, id: Path<i32>, payload: ValidatedJson<ReplaceUser>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
     let user_id = id.into_inner();
-    let result = User::find_by_id(user_id.clone()).one(&app_state.db).await;
-
-    match result {
-        Ok(model) => {
-            match model {
-                None => {
-                    let message = format!("User with ID `{}`, does not exist", user_id.clone());
-                    let response = ApiResponse { message };
-                    Ok(HttpResponse::NotFound().json(response))
-                }
-                Some(user_model) => {
-                    let mut user = user_model.into_active_model();
-                    user.username = Set(payload.username.clone());
-                    user.firstname = Set(payload.firstname.clone());
-                    user.lastname = Set(payload.lastname.clone());
-                    user.email = Set(payload.email.clone());
-                    user.password = Set(payload.password.clone());
-                    user.is_active = Set(payload.is_active.clone());
-                    user.is_admin = Set(payload.is_admin.clone());
-                    user.is_superadmin = Set(payload.is_superadmin.clone());
-                    user.updated_at = Set(Option::from(
-                        NaiveDateTime::new(
-                            NaiveDate::from(Utc::now().date_naive()),
-                            NaiveTime::from(Utc::now().time()),
-                        )
-                    ));
-
-                    let update = user.update(&app_state.db).await;
-                    match update {
-                        Ok(response) => Ok(HttpResponse::Ok().json(response)),
-                        Err(err) => {
-                            let response = ApiResponse { message: err.to_string() };
-                            Ok(HttpResponse::BadRequest().json(response))
-                        }
-                    }
-                }
-            }
-        }
-        Err(err) => {
-            let response = ApiResponse { message: err.to_string() };
-            Ok(HttpResponse::BadRequest().json(response))
-        }
+    current_user.require_self_or_admin(user_id)?;
+    let user_model = find_user(&app_state.db, user_id).await?;
+    check_if_match(&request, &etag(user_model.version))?;
+
+    let (version, was_active) = (user_model.version, user_model.is_active.unwrap_or(false));
+    let user = apply_replacement(&current_user, user_model, &payload)?;
+    let email_change = matches!(user.pending_email, Set(Some(_)));
+
+    let user = save_versioned(&app_state.db, user, user_id, version)
+        .await?
+        .ok_or_else(|| write_conflict(&request))?;
+    apply_activation(&app_state, was_active, &user).await?;
+    if email_change {
+        send_verification_email(&app_state, user.id, email_verification::change_email(&user));
     }
+    Ok(HttpResponse::Ok()
+        .insert_header(ETag(etag(user.version)))
+        .json(UserResponse::for_viewer(user, Some(&current_user))))
 }
 
+/// Soft deletes a user, which frees their username and email and signs them out everywhere.
+/// With `?purge=true` a superadmin can remove the row for good, deleted or not.
 #[delete("/{id}")]
-async fn delete_user(id: Path<i32>, app_state: Data<AppState>) -> Result<impl Responder, Error> {
+async fn delete_user(request: HttpRequest, id: Path<i32>, query: Query<DeleteQuery>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
     let user_id = id.into_inner();
-    let result = User::find_by_id(user_id).one(&app_state.db).await;
-
-    match result {
-        Ok(model) => {
-            match model {
-                None => {
-                    let message = format!("User with ID `{}`, does not exist", user_id.clone());
-                    let response = ApiResponse { message };
-                    Ok(HttpResponse::NotFound().json(response))
-                }
-                Some(user_model) => {
-                    let user = user_model.into_active_model();
-                    let res = user.delete(&app_state.db).await;
-
-                    match res {
-                        Ok(delete_result) => {
-                            let message = format!("Deleted {} user with Id {}", delete_result.rows_affected, user_id.clone());
-                            let response = ApiResponse { message };
-                            Ok(HttpResponse::Ok().json(response))
-                        }
-                        Err(err) => {
-                            let response = ApiResponse { message: err.to_string() };
-                            Ok(HttpResponse::BadRequest().json(response))
-                        }
-                    }
-                }
-            }
-        }
-        Err(err) => {
-            let response = ApiResponse { message: err.to_string() };
-            Ok(HttpResponse::BadRequest().json(response))
+    current_user.require_admin()?;
+    let purge = query.purge.unwrap_or(false);
+    if purge {
+        current_user.require_superadmin()?;
+    }
+
+    let user_model = if purge { find_any_user(&app_state.db, user_id).await? } else { find_user(&app_state.db, user_id).await? };
+    if user_model.is_superadmin.unwrap_or(false) {
+        current_user.require_superadmin()?;
+    }
+    check_if_match(&request, &etag(user_model.version))?;
+
+    let rows_affected = if purge {
+        User::delete_many()
+            .filter(Column::Id.eq(user_id))
+            .filter(Column::Version.eq(user_model.version))
+            .exec(&app_state.db)
+            .await?
+            .rows_affected
+    } else {
+        let version = user_model.version;
+        let mut user = user_model.into_active_model();
+        user.deleted_at = Set(Some(Utc::now().fixed_offset()));
+        save_versioned(&app_state.db, user, user_id, version).await?.map_or(0, |_| 1)
+    };
+    if rows_affected == 0 {
+        return Err(write_conflict(&request));
+    }
+    if !purge {
+        app_state.revocations.revoke_all(&app_state.db, user_id).await?;
+    }
+
+    let message = format!("{} {} user with Id {}", if purge { "Purged" } else { "Deleted" }, rows_affected, user_id);
+    let response = ApiResponse { message };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[post("/{id}/restore")]
+async fn restore_user(request: HttpRequest, id: Path<i32>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let user_id = id.into_inner();
+    current_user.require_admin()?;
+    let user_model = find_any_user(&app_state.db, user_id).await?;
+    if user_model.deleted_at.is_none() {
+        return Err(AppError::Conflict(format!("User with ID `{}` is not deleted", user_id)));
+    }
+    if user_model.is_superadmin.unwrap_or(false) {
+        current_user.require_superadmin()?;
+    }
+    check_if_match(&request, &etag(user_model.version))?;
+
+    // fails with a conflict when the username or email has been taken in the meantime
+    let version = user_model.version;
+    let mut user = user_model.into_active_model();
+    user.deleted_at = Set(None);
+    let user = save_versioned(&app_state.db, user, user_id, version)
+        .await?
+        .ok_or_else(|| write_conflict(&request))?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(ETag(etag(user.version)))
+        .json(UserResponse::new(user, UserView::Admin)))
+}
+
+/// A user's login attempts, newest first unless `sort` says otherwise. Users can read their own
+/// history, admins anyone's.
+#[get("/{id}/logins")]
+async fn get_user_logins(request: HttpRequest, id: Path<i32>, query: Query<PaginationQuery>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let user_id = id.into_inner();
+    current_user.require_self_or_admin(user_id)?;
+    find_user(&app_state.db, user_id).await?;
+
+    let sort = Sort::parse(query.sort().or(Some("-created_at")), LOGIN_FIELDS, ("id", login_event::Column::Id))?;
+    let filters = Filters::from_request(&request, LOGIN_FIELDS)?;
+    let select = filters.apply(LoginEvent::find().filter(login_event::Column::UserId.eq(user_id)));
+
+    if let Some(cursor) = CursorPagination::from_query(&query, sort.clone())? {
+        let page = cursor.fetch(select, &app_state.db).await?.map(LoginEventResponse::from);
+        return Ok(page.response(&request, "logins"));
+    }
+
+    let pagination = Pagination::from_query(&query)?;
+    let total = select.clone().count(&app_state.db).await?;
+    let events: Vec<LoginEventResponse> = pagination.paginate(sort.apply(select, false))
+        .all(&app_state.db)
+        .await?
+        .into_iter()
+        .map(LoginEventResponse::from)
+        .collect();
+
+    Ok(pagination.response(&request, "logins", events, total))
+}
+
+/// Sends the email verification link of an inactive user again.
+#[post("/{id}/verification-email")]
+async fn resend_verification_email(id: Path<i32>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let user_id = id.into_inner();
+    current_user.require_admin()?;
+    let user = find_user(&app_state.db, user_id).await?;
+    if user.is_active.unwrap_or(false) {
+        return Err(AppError::Conflict(format!("User with ID `{}` is already active", user_id)));
+    }
+
+    app_state.mailer.send(email_verification::email(&user)).await?;
+
+    let response = ApiResponse { message: format!("Sent a verification email to user with Id {}", user_id) };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Lifts a lockout from too many failed logins.
+#[post("/{id}/unlock")]
+async fn unlock_user(id: Path<i32>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let user_id = id.into_inner();
+    current_user.require_admin()?;
+    let user_model = find_user(&app_state.db, user_id).await?;
+    if user_model.is_superadmin.unwrap_or(false) {
+        current_user.require_superadmin()?;
+    }
+
+    throttle::reset(&app_state.db, &ThrottleKey::User(user_id)).await?;
+
+    let response = ApiResponse { message: format!("Unlocked user with Id {}", user_id) };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Makes `user_id` use two-factor authentication. It takes effect at their next login, which
+/// also lets them set it up if they haven't yet.
+#[post("/{id}/mfa/require")]
+async fn require_mfa(id: Path<i32>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let user_id = id.into_inner();
+    set_mfa_required(&current_user, &app_state, user_id, true).await?;
+
+    let response = ApiResponse { message: format!("Two-factor authentication is now required for user with Id {}", user_id) };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[delete("/{id}/mfa/require")]
+async fn unrequire_mfa(id: Path<i32>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let user_id = id.into_inner();
+    set_mfa_required(&current_user, &app_state, user_id, false).await?;
+
+    let response = ApiResponse { message: format!("Two-factor authentication is now optional for user with Id {}", user_id) };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Turns off two-factor authentication of a user who lost their authenticator app and
+/// recovery codes. If it is required, they set it up again at their next login.
+#[delete("/{id}/mfa")]
+async fn reset_mfa(id: Path<i32>, current_user: CurrentUser, app_state: Data<AppState>) -> Result<impl Responder, AppError> {
+    let user_id = id.into_inner();
+    current_user.require_admin()?;
+    let user_model = find_user(&app_state.db, user_id).await?;
+    if user_model.is_superadmin.unwrap_or(false) {
+        current_user.require_superadmin()?;
+    }
+
+    let txn = app_state.db.begin().await?;
+    mfa::disable(&txn, user_id).await?;
+    txn.commit().await?;
+
+    let response = ApiResponse { message: format!("Reset two-factor authentication of user with Id {}", user_id) };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+fn render_user(user: Model, viewer: Option<&CurrentUser>, fields: Option<&Fields<Column>>) -> JsonValue {
+    let user = UserResponse::for_viewer(user, viewer);
+    match fields {
+        Some(fields) => fields.project(&user),
+        None => serde_json::to_value(user).unwrap_or_default(),
+    }
+}
+
+async fn find_user(db: &DatabaseConnection, user_id: i32) -> Result<Model, AppError> {
+    User::find_existing()
+        .filter(Column::Id.eq(user_id))
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound(format!("User with ID `{}`, does not exist", user_id)))
+}
+
+/// Like `find_user`, but also finds soft deleted users.
+async fn find_any_user(db: &DatabaseConnection, user_id: i32) -> Result<Model, AppError> {
+    User::find_by_id(user_id)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound(format!("User with ID `{}`, does not exist", user_id)))
+}
+
+/// The changes of a partial `application/json` update, where absent fields are left as they are.
+fn apply_partial(current_user: &CurrentUser, user_model: Model, payload: &PatchUser) -> Result<ActiveModel, AppError> {
+    authorize_changes(
+        current_user,
+        &user_model,
+        payload.is_active.or(user_model.is_active),
+        payload.is_admin.or(user_model.is_admin),
+        payload.is_superadmin.or(user_model.is_superadmin),
+    )?;
+
+    let mut user = user_model.into_active_model();
+    user.username = Set(payload.username.clone().or(user.username.unwrap()));
+    user.firstname = Set(payload.firstname.clone().or(user.firstname.unwrap()));
+    user.lastname = Set(payload.lastname.clone().or(user.lastname.unwrap()));
+    if let Some(email) = &payload.email {
+        assign_email(current_user, &mut user, email);
+    }
+    if let Some(password) = &payload.password {
+        user.password = Set(Some(PasswordHasher::new().hash(password)?));
+    }
+    user.is_active = Set(payload.is_active.or(user.is_active.unwrap()));
+    user.is_admin = Set(payload.is_admin.or(user.is_admin.unwrap()));
+    user.is_superadmin = Set(payload.is_superadmin.or(user.is_superadmin.unwrap()));
+
+    Ok(user)
+}
+
+/// The changes of a full replacement, either from `PUT` or from a patched user document.
+fn apply_replacement(current_user: &CurrentUser, user_model: Model, payload: &ReplaceUser) -> Result<ActiveModel, AppError> {
+    authorize_changes(
+        current_user,
+        &user_model,
+        Some(payload.is_active),
+        Some(payload.is_admin),
+        Some(payload.is_superadmin),
+    )?;
+
+    let mut user = user_model.into_active_model();
+    user.username = Set(Some(payload.username.clone()));
+    user.firstname = Set(payload.firstname.clone());
+    user.lastname = Set(payload.lastname.clone());
+    assign_email(current_user, &mut user, &payload.email);
+    if let Some(password) = &payload.password {
+        user.password = Set(Some(PasswordHasher::new().hash(password)?));
+    }
+    user.is_active = Set(Some(payload.is_active));
+    user.is_admin = Set(Some(payload.is_admin));
+    user.is_superadmin = Set(Some(payload.is_superadmin));
+
+    Ok(user)
+}
+
+/// Writes `new_email` to `user`. Users changing their own address have to prove they can read
+/// the new one, or password reset links would go to an address nobody checked, so it waits in
+/// `pending_email` until they open the link sent to it and the current one stays in use. A typo
+/// can't lock anyone out that way. Changes by admins are trusted and take effect right away.
+fn assign_email(current_user: &CurrentUser, user: &mut ActiveModel, new_email: &str) {
+    let is_admin = current_user.is_admin || current_user.is_superadmin;
+    let current = user.email.as_ref().as_deref();
+    if current == Some(new_email.to_lowercase().as_str()) {
+        return;
+    }
+
+    if is_admin {
+        user.email = Set(Some(new_email.to_string()));
+        user.pending_email = Set(None);
+    } else {
+        user.pending_email = Set(Some(new_email.to_string()));
+    }
+}
+
+/// Writes `user` and bumps its version, unless the row has changed since `version` was read.
+/// Returns `None` in that case, so two writers can't silently overwrite each other.
+async fn save_versioned(db: &DatabaseConnection, mut user: ActiveModel, user_id: i32, version: i32) -> Result<Option<Model>, AppError> {
+    user.version = Set(version + 1);
+    // `update_many` skips the entity's hooks, so the timestamps are set here
+    let user = user.before_save(db, false).await?;
+    let updated = User::update_many()
+        .set(user)
+        .filter(Column::Id.eq(user_id))
+        .filter(Column::Version.eq(version))
+        .exec_with_returning(db)
+        .await?;
+    Ok(updated.into_iter().next())
+}
+
+/// Finishes a login once every factor is checked: records it and issues a token pair.
+async fn complete_login(request: &HttpRequest, app_state: &AppState, user: &Model, identifier: &str) -> Result<LoginResponse, AppError> {
+    // `last_login` is part of the user's representation, so the version moves with it
+    User::update_many()
+        .col_expr(Column::LastLogin, Expr::value(Utc::now().fixed_offset()))
+        .col_expr(Column::Version, Expr::col(Column::Version).add(1))
+        .filter(Column::Id.eq(user.id))
+        .exec(&app_state.db)
+        .await?;
+    // failures from the address still count, or logging into one's own account would clear them
+    throttle::reset(&app_state.db, &ThrottleKey::User(user.id)).await?;
+    logins::record(&app_state.db, request, Some(user.id), identifier, LoginOutcome::Success).await;
+
+    let tokens = tokens::issue(&app_state.db, user.id, user.email.clone().unwrap_or_default()).await?;
+    Ok(LoginResponse {
+        token: tokens.token,
+        refresh_token: tokens.refresh_token,
+    })
+}
+
+/// The user a pending login from `login` belongs to.
+async fn mfa_login(app_state: &AppState, mfa_token: String) -> Result<(Claims, Model), AppError> {
+    let jwt = JSONWebToken { keys: get_jwt_keys() };
+    let claims = jwt.decode(mfa_token, TokenType::MfaPending).map_err(AppError::InvalidToken)?.claims;
+    if app_state.revocations.is_revoked(&app_state.db, &claims).await? {
+        return Err(AppError::RevokedToken);
+    }
+
+    let user = User::find_existing()
+        .filter(Column::Id.eq(claims.id))
+        .one(&app_state.db)
+        .await?
+        .ok_or(AppError::UnknownUser)?;
+    if !user.is_active.unwrap_or(false) {
+        return Err(AppError::InactiveUser);
+    }
+
+    Ok((claims, user))
+}
+
+async fn start_mfa_setup(app_state: &AppState, user: &Model) -> Result<MfaSetupResponse, AppError> {
+    if user.mfa_enabled_at.is_some() {
+        return Err(AppError::Conflict("Two-factor authentication is already enabled".to_string()));
+    }
+
+    let secret = mfa::start_enrollment(&app_state.db, user.id).await?;
+    let otpauth_uri = mfa::otpauth_uri(user, &secret)?;
+    Ok(MfaSetupResponse { secret, otpauth_uri })
+}
+
+/// Checks a code from the authenticator app or a recovery code of `user`. Wrong codes count
+/// like wrong passwords, so codes can't be guessed faster than passwords.
+async fn verify_second_factor(request: &HttpRequest, app_state: &AppState, user: &Model, code: &str) -> Result<bool, AppError> {
+    if user.mfa_secret.is_none() {
+        return Err(AppError::Conflict("Two-factor authentication has to be set up first".to_string()));
+    }
+
+    let mut throttle_keys = vec![ThrottleKey::User(user.id)];
+    throttle_keys.extend(logins::client_ip(request).map(ThrottleKey::Ip));
+    if let Some(seconds) = throttle::retry_after(&app_state.db, &throttle_keys).await? {
+        return Err(AppError::TooManyRequests(seconds));
+    }
+
+    let verified = mfa::verify(&app_state.db, user, code).await?;
+    if !verified {
+        throttle::record_failure(&app_state.db, &throttle_keys).await?;
+    }
+    Ok(verified)
+}
+
+// a wrong code from a user who is already logged in is a wrong input rather than a failed login
+fn invalid_code() -> AppError {
+    AppError::Validation(BTreeMap::from([("code".to_string(), vec!["is not valid".to_string()])]))
+}
+
+async fn set_mfa_required(current_user: &CurrentUser, app_state: &AppState, user_id: i32, required: bool) -> Result<(), AppError> {
+    current_user.require_admin()?;
+    let user_model = find_user(&app_state.db, user_id).await?;
+    if user_model.is_superadmin.unwrap_or(false) {
+        current_user.require_superadmin()?;
+    }
+
+    User::update_many()
+        .col_expr(Column::MfaRequired, Expr::value(required))
+        .col_expr(Column::UpdatedAt, Expr::value(Utc::now().fixed_offset()))
+        .col_expr(Column::Version, Expr::col(Column::Version).add(1))
+        .filter(Column::Id.eq(user_id))
+        .exec(&app_state.db)
+        .await?;
+    Ok(())
+}
+
+/// Turns on two-factor authentication along with the first recovery codes, in one transaction,
+/// so it is never on without a way back in for someone who loses their authenticator.
+async fn enable_mfa_with_codes(app_state: &AppState, user_id: i32) -> Result<Vec<String>, AppError> {
+    let txn = app_state.db.begin().await?;
+    let recovery_codes = mfa::enable(&txn, user_id).await?;
+    txn.commit().await?;
+    Ok(recovery_codes)
+}
+
+/// Sends the email verification link of a new user, or of one who changed their address, in
+/// the background. The change is saved either way; a failed email can be sent again by an
+/// admin, or by changing the address again.
+fn send_verification_email(app_state: &Data<AppState>, user_id: i32, email: Email) {
+    let app_state = app_state.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(err) = app_state.mailer.send(email).await {
+            log::error!("Error sending verification email to user {}: {}", user_id, err);
         }
+    });
+}
+
+/// Makes a change of `is_active` take effect right away. A deactivated user is also signed
+/// out everywhere.
+async fn apply_activation(app_state: &AppState, was_active: bool, user: &Model) -> Result<(), AppError> {
+    match (was_active, user.is_active.unwrap_or(false)) {
+        (true, false) => app_state.revocations.revoke_all(&app_state.db, user.id).await?,
+        (false, true) => app_state.revocations.forget(user.id),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Checks that `current_user` may write the given flag values to `target`. Only admins can
+/// (de)activate accounts, only superadmins can grant or revoke admin flags, and accounts of
+/// superadmins can only be changed by themselves or another superadmin.
+fn authorize_changes(
+    current_user: &CurrentUser,
+    target: &Model,
+    is_active: Option<bool>,
+    is_admin: Option<bool>,
+    is_superadmin: Option<bool>,
+) -> Result<(), AppError> {
+    if current_user.id != target.id && target.is_superadmin.unwrap_or(false) {
+        current_user.require_superadmin()?;
     }
+    // a missing flag means `false`, so writing `false` over `NULL` is not a change
+    let changed = |new: Option<bool>, old: Option<bool>| new.unwrap_or(false) != old.unwrap_or(false);
+    if changed(is_active, target.is_active) {
+        current_user.require_admin()?;
+    }
+    if changed(is_admin, target.is_admin) || changed(is_superadmin, target.is_superadmin) {
+        current_user.require_superadmin()?;
+    }
+
+    Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::revocation::RevocationList;
+    use crate::utils::config::get_db_connection;
+    use crate::utils::mail::MemoryMailer;
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use migration::{Migrator, MigratorTrait};
+    use uuid::Uuid;
+
+    // the token in the link of a password reset email
+    fn reset_token(email: &Email) -> String {
+        let link = email.body.split_whitespace().find(|word| word.contains("token=")).expect("no link in the email");
+        let url = url::Url::parse(link).unwrap();
+        url.query_pairs().find(|(name, _)| name == "token").unwrap().1.into_owned()
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs the PostgreSQL database at `DATABASE_URL` and a `SECRET`, like the server"]
+    async fn resets_a_forgotten_password() {
+        let db = get_db_connection().await;
+        Migrator::up(&db, None).await.unwrap();
+        let mailer = MemoryMailer::new();
+        let app_state = Data::new(AppState { db, revocations: RevocationList::new(), mailer: Box::new(mailer.clone()) });
+        let app = test::init_service(App::new().app_data(app_state.clone()).configure(crate::users::urls::routes)).await;
+
+        let name = format!("reset-{}", Uuid::new_v4().simple());
+        let address = format!("{}@example.com", name);
+        let user = ActiveModel {
+            username: Set(Some(name.clone())),
+            email: Set(Some(address.clone())),
+            password: Set(Some(PasswordHasher::new().hash("oldpass123").unwrap())),
+            is_active: Set(Some(true)),
+            ..Default::default()
+        }.insert(&app_state.db).await.unwrap();
+
+        let login_with = |password: &str| test::TestRequest::post()
+            .uri("/users/login")
+            .set_json(json!({ "username": name, "password": password }))
+            .to_request();
+        let old_session: JsonValue = test::call_and_read_body_json(&app, login_with("oldpass123")).await;
+
+        let forgot = test::TestRequest::post().uri("/auth/password/forgot").set_json(json!({ "email": address })).to_request();
+        assert_eq!(test::call_service(&app, forgot).await.status(), StatusCode::ACCEPTED);
+        // the email is sent in the background
+        let mut sent = vec![];
+        for _ in 0..100 {
+            sent = mailer.sent().into_iter().filter(|email| email.to == address).collect();
+            if !sent.is_empty() {
+                break;
+            }
+            actix_web::rt::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(sent.len(), 1, "the reset email was not captured");
+        let token = reset_token(&sent[0]);
+
+        let reset = || test::TestRequest::post()
+            .uri("/auth/password/reset")
+            .set_json(json!({ "token": token, "password": "newpass123" }))
+            .to_request();
+        assert_eq!(test::call_service(&app, reset()).await.status(), StatusCode::OK);
+        assert_eq!(test::call_service(&app, reset()).await.status(), StatusCode::BAD_REQUEST);
+
+        let with_old_session = test::TestRequest::get()
+            .uri(&format!("/users/{}", user.id))
+            .insert_header((AUTHORIZATION, format!("Bearer {}", old_session["token"].as_str().unwrap())))
+            .to_request();
+        // rejected by the middleware, which fails the call instead of answering
+        let err = test::try_call_service(&app, with_old_session).await.expect_err("the old session still works");
+        assert_eq!(err.as_response_error().status_code(), StatusCode::UNAUTHORIZED);
+
+        assert_eq!(test::call_service(&app, login_with("oldpass123")).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(test::call_service(&app, login_with("newpass123")).await.status(), StatusCode::OK);
+
+        User::delete_by_id(user.id).exec(&app_state.db).await.unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+use crate::utils::config::{get_invitation_ttl, get_invitation_url};
+use crate::utils::mail::{link, Email};
+use crate::utils::secret::{hash_token, random_token};
+use chrono::{DateTime, FixedOffset, TimeDelta, Utc};
+use entity::invitation::{ActiveModel, Column, Entity as Invitation, Model};
+use sea_orm::sea_query::Expr;
+use sea_orm::ActiveValue::{NotSet, Set};
+use sea_orm::{ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+
+/// What an invited user becomes. Stored as text in `invitation.role`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Admin,
+    Superadmin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Admin => "admin",
+            Role::Superadmin => "superadmin",
+        }
+    }
+
+    pub fn parse(role: &str) -> Self {
+        match role {
+            "admin" => Role::Admin,
+            "superadmin" => Role::Superadmin,
+            _ => Role::User,
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        *self != Role::User
+    }
+}
+
+/// The fields of invitations that can be filtered and sorted by.
+pub const INVITATION_FIELDS: &[(&str, Column)] = &[
+    ("email", Column::Email),
+    ("role", Column::Role),
+    ("invited_by", Column::InvitedBy),
+    ("accepted_by", Column::AcceptedBy),
+    ("expires_at", Column::ExpiresAt),
+    ("accepted_at", Column::AcceptedAt),
+    ("revoked_at", Column::RevokedAt),
+    ("created_at", Column::CreatedAt),
+];
+
+/// Invites `email` and returns the invitation with its token. Only the token's hash is
+/// stored. Pending invitations of the same address, in any case, are revoked, so only the
+/// newest link works.
+pub async fn issue<C: ConnectionTrait>(db: &C, email: &str, role: Role, invited_by: i32) -> Result<(Model, String), DbErr> {
+    let email = email.to_lowercase();
+    let now = Utc::now().fixed_offset();
+    pending(Invitation::update_many().col_expr(Column::RevokedAt, Expr::value(now)), now)
+        .filter(Column::Email.eq(&email))
+        .exec(db)
+        .await?;
+
+    let token = random_token();
+    let invitation = ActiveModel {
+        id: NotSet,
+        email: Set(email),
+        role: Set(role.as_str().to_string()),
+        token_hash: Set(hash_token(&token)),
+        invited_by: Set(Some(invited_by)),
+        accepted_by: Set(None),
+        expires_at: Set(now + TimeDelta::from_std(get_invitation_ttl()).unwrap_or_default()),
+        accepted_at: Set(None),
+        revoked_at: Set(None),
+        created_at: Set(now),
+    };
+
+    Ok((invitation.insert(db).await?, token))
+}
+
+/// The invitation `token` belongs to, unless it is unknown, accepted, revoked or expired.
+pub async fn find_pending<C: ConnectionTrait>(db: &C, token: &str) -> Result<Option<Model>, DbErr> {
+    let now = Utc::now().fixed_offset();
+    pending(Invitation::find(), now)
+        .filter(Column::TokenHash.eq(hash_token(token)))
+        .one(db)
+        .await
+}
+
+/// Marks the invitation as accepted by `user_id`. Returns `false` when it isn't pending
+/// anymore, e.g. because it was revoked or accepted by a concurrent request.
+pub async fn accept<C: ConnectionTrait>(db: &C, invitation_id: i32, user_id: i32) -> Result<bool, DbErr> {
+    let now = Utc::now().fixed_offset();
+    let accepted = pending(Invitation::update_many(), now)
+        .col_expr(Column::AcceptedAt, Expr::value(now))
+        .col_expr(Column::AcceptedBy, Expr::value(user_id))
+        .filter(Column::Id.eq(invitation_id))
+        .exec(db)
+        .await?;
+    Ok(accepted.rows_affected == 1)
+}
+
+/// The email with the link to accept `invitation`.
+pub fn email(invitation: &Model, token: &str) -> Email {
+    let link = link(&get_invitation_url(), token);
+
+    Email {
+        to: invitation.email.clone(),
+        subject: "You have been invited".to_string(),
+        body: format!(
+            "Hi,\n\n\
+            you have been invited to create an account. To choose a username and password, open \
+            this link before {}:\n\n{}\n\n\
+            If you didn't expect this email, you can ignore it.\n",
+            invitation.expires_at.format("%Y-%m-%d %H:%M UTC"),
+            link,
+        ),
+    }
+}
+
+fn pending<Q: QueryFilter>(query: Q, now: DateTime<FixedOffset>) -> Q {
+    query
+        .filter(Column::AcceptedAt.is_null())
+        .filter(Column::RevokedAt.is_null())
+        .filter(Column::ExpiresAt.gt(now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult, Transaction};
+
+    fn invitation(email: &str) -> Model {
+        let now = Utc::now().fixed_offset();
+        Model {
+            id: 2,
+            email: email.to_string(),
+            role: "user".to_string(),
+            token_hash: "hash".to_string(),
+            invited_by: Some(1),
+            accepted_by: None,
+            expires_at: now,
+            accepted_at: None,
+            revoked_at: None,
+            created_at: now,
+        }
+    }
+
+    #[actix_web::test]
+    async fn reinviting_revokes_earlier_invitations_in_any_case() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([MockExecResult { last_insert_id: 0, rows_affected: 1 }])
+            .append_query_results([vec![invitation("alice@x.com")]])
+            .into_connection();
+
+        issue(&db, "Alice@X.com", Role::User, 1).await.unwrap();
+
+        let log = db.into_transaction_log();
+        let statements: Vec<String> = log.iter().flat_map(Transaction::statements).map(ToString::to_string).collect();
+        assert_eq!(statements.len(), 2);
+        assert!(statements[0].starts_with("UPDATE"));
+        assert!(statements[0].contains(r#""email" = 'alice@x.com'"#), "{}", statements[0]);
+        assert!(statements[1].starts_with("INSERT"));
+        assert!(statements[1].contains("'alice@x.com'"), "{}", statements[1]);
+        assert!(!statements.iter().any(|statement| statement.contains("Alice")));
+    }
+}
@@ -0,0 +1,128 @@
+use crate::utils::config::get_trusted_proxies;
+use actix_web::http::header::{FORWARDED, USER_AGENT, X_FORWARDED_FOR};
+use actix_web::HttpRequest;
+use entity::login_event::ActiveModel;
+use entity::login_event::Column;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ActiveModelTrait, DatabaseConnection};
+use std::net::IpAddr;
+
+/// Longest user agent or identifier that is stored, the rest is cut off.
+const MAX_TEXT_LENGTH: usize = 512;
+
+/// How a login attempt ended. Stored as text in `login_event.outcome`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoginOutcome {
+    Success,
+    WrongPassword,
+    UnknownUser,
+    /// turned away without checking the password, because of too many failures
+    Locked,
+    /// the right password for an account that is not active
+    Inactive,
+    /// the right password, waiting for the second factor
+    MfaPending,
+    /// the right password, but a wrong second factor
+    WrongMfaCode,
+}
+
+impl LoginOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoginOutcome::Success => "success",
+            LoginOutcome::WrongPassword => "wrong_password",
+            LoginOutcome::UnknownUser => "unknown_user",
+            LoginOutcome::Locked => "locked",
+            LoginOutcome::Inactive => "inactive",
+            LoginOutcome::MfaPending => "mfa_pending",
+            LoginOutcome::WrongMfaCode => "wrong_mfa_code",
+        }
+    }
+}
+
+/// The fields of a user's login history that can be filtered and sorted by.
+pub const LOGIN_FIELDS: &[(&str, Column)] = &[
+    ("outcome", Column::Outcome),
+    ("created_at", Column::CreatedAt),
+];
+
+/// Writes a login attempt to `login_event`, with the username or email it was made with.
+/// `user_id` is `None` when no user goes by `identifier`. A failed write is logged and doesn't
+/// fail the login.
+pub async fn record(db: &DatabaseConnection, request: &HttpRequest, user_id: Option<i32>, identifier: &str, outcome: LoginOutcome) {
+    let user_agent = request.headers().get(USER_AGENT).and_then(|value| value.to_str().ok());
+    let event = ActiveModel {
+        user_id: Set(user_id),
+        username: Set(truncate(identifier)),
+        outcome: Set(outcome.as_str().to_string()),
+        ip: Set(client_ip(request)),
+        user_agent: Set(user_agent.map(truncate)),
+        ..Default::default()
+    };
+
+    if let Err(err) = event.insert(db).await {
+        log::error!("Error recording {} login of '{}': {}", outcome.as_str(), identifier, err);
+    }
+}
+
+/// The address of the client. That's the peer, unless the peer is one of `TRUSTED_PROXIES`:
+/// then it's the last address in `Forwarded` or `X-Forwarded-For` that isn't a trusted proxy
+/// too. Anyone can set these headers, so only the entries added by trusted proxies count.
+pub fn client_ip(request: &HttpRequest) -> Option<String> {
+    let peer = request.peer_addr()?.ip();
+    let proxies = get_trusted_proxies();
+    if !proxies.contains(&peer) {
+        return Some(peer.to_string());
+    }
+
+    let forwarded = forwarded_for(request).unwrap_or_default();
+    let client = forwarded.into_iter().rev().find(|address| !proxies.contains(address)).unwrap_or(peer);
+    Some(client.to_string())
+}
+
+// the addresses a request was forwarded for, from the client to the last proxy, or `None`
+// when there are no forwarding headers or one of the entries isn't an address
+fn forwarded_for(request: &HttpRequest) -> Option<Vec<IpAddr>> {
+    let headers = request.headers();
+    if let Some(header) = headers.get(FORWARDED) {
+        // `Forwarded: for=192.0.2.60;proto=http, for="[2001:db8::1]:4711"`
+        return header
+            .to_str()
+            .ok()?
+            .split(',')
+            .map(|element| {
+                let node = element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))?
+                    .1
+                    .trim()
+                    .trim_matches('"');
+                parse_node(node)
+            })
+            .collect();
+    }
+
+    headers
+        .get(X_FORWARDED_FOR)?
+        .to_str()
+        .ok()?
+        .split(',')
+        .map(|node| parse_node(node.trim()))
+        .collect()
+}
+
+// an address with or without a port, IPv6 ones in brackets when there is a port
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(address) = node.parse::<IpAddr>() {
+        return Some(address);
+    }
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    node.split_once(':')?.0.parse().ok()
+}
+
+fn truncate(text: &str) -> String {
+    text.chars().take(MAX_TEXT_LENGTH).collect()
+}
@@ -0,0 +1,169 @@
+use crate::utils::config::get_mfa_issuer;
+use crate::utils::errors::AppError;
+use crate::utils::secret::hash_token;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::Utc;
+use entity::mfa_recovery_code::{ActiveModel, Column, Entity as MfaRecoveryCode};
+use entity::user::{Column as UserColumn, Entity as User, Model as UserModel};
+use sea_orm::prelude::DateTimeWithTimeZone;
+use sea_orm::sea_query::Expr;
+use sea_orm::ActiveValue::{NotSet, Set};
+use sea_orm::{ColumnTrait, Condition, ConnectionTrait, DbErr, EntityTrait, QueryFilter};
+use subtle::ConstantTimeEq;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+// RFC 6238 defaults, which is what authenticator apps expect
+const DIGITS: usize = 6;
+const STEP: u64 = 30;
+// codes of the steps right before and after the current one are accepted too, for clocks
+// that are a little off
+const SKEW: u64 = 1;
+
+const RECOVERY_CODES: usize = 10;
+// no `0`, `1`, `i`, `l` or `o`, which are easily mixed up when typed from paper
+const RECOVERY_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// Gives `user_id` a new secret to set up their authenticator app with and returns it, base32
+/// encoded. Two-factor authentication only starts once a code for it was checked by `enable`.
+pub async fn start_enrollment<C: ConnectionTrait>(db: &C, user_id: i32) -> Result<String, DbErr> {
+    let mut bytes = [0u8; 20];
+    OsRng.fill_bytes(&mut bytes);
+    let secret = Secret::Raw(bytes.to_vec()).to_encoded().to_string();
+
+    User::update_many()
+        .col_expr(UserColumn::MfaSecret, Expr::value(&secret))
+        .col_expr(UserColumn::MfaLastStep, Expr::value(Option::<i64>::None))
+        .filter(UserColumn::Id.eq(user_id))
+        .filter(UserColumn::MfaEnabledAt.is_null())
+        .exec(db)
+        .await?;
+
+    Ok(secret)
+}
+
+/// The `otpauth://` URI authenticator apps are set up with, usually shown as a QR code.
+pub fn otpauth_uri(user: &UserModel, secret: &str) -> Result<String, AppError> {
+    Ok(totp(user, secret)?.get_url())
+}
+
+/// Turns on two-factor authentication for `user_id` and returns their first recovery codes.
+/// Run it in a transaction, or a failed insert leaves it on without recovery codes.
+pub async fn enable<C: ConnectionTrait>(db: &C, user_id: i32) -> Result<Vec<String>, DbErr> {
+    let now = Utc::now().fixed_offset();
+    User::update_many()
+        .col_expr(UserColumn::MfaEnabledAt, Expr::value(now))
+        .col_expr(UserColumn::UpdatedAt, Expr::value(now))
+        .col_expr(UserColumn::Version, Expr::col(UserColumn::Version).add(1))
+        .filter(UserColumn::Id.eq(user_id))
+        .exec(db)
+        .await?;
+
+    issue_recovery_codes(db, user_id).await
+}
+
+/// Turns off two-factor authentication for `user_id`, forgetting the secret and recovery codes.
+pub async fn disable<C: ConnectionTrait>(db: &C, user_id: i32) -> Result<(), DbErr> {
+    let now = Utc::now().fixed_offset();
+    User::update_many()
+        .col_expr(UserColumn::MfaSecret, Expr::value(Option::<String>::None))
+        .col_expr(UserColumn::MfaEnabledAt, Expr::value(Option::<DateTimeWithTimeZone>::None))
+        .col_expr(UserColumn::MfaLastStep, Expr::value(Option::<i64>::None))
+        .col_expr(UserColumn::UpdatedAt, Expr::value(now))
+        .col_expr(UserColumn::Version, Expr::col(UserColumn::Version).add(1))
+        .filter(UserColumn::Id.eq(user_id))
+        .exec(db)
+        .await?;
+
+    MfaRecoveryCode::delete_many().filter(Column::UserId.eq(user_id)).exec(db).await?;
+    Ok(())
+}
+
+/// Checks a second factor of `user`: a code from their authenticator app, or one of their
+/// recovery codes once two-factor authentication is enabled. Either works only once.
+pub async fn verify<C: ConnectionTrait>(db: &C, user: &UserModel, code: &str) -> Result<bool, AppError> {
+    let Some(secret) = user.mfa_secret.as_deref() else {
+        return Ok(false);
+    };
+    let code = code.trim();
+    if code.len() == DIGITS && code.bytes().all(|byte| byte.is_ascii_digit()) {
+        return check_code(db, user, secret, code).await;
+    }
+    if user.mfa_enabled_at.is_none() {
+        return Ok(false);
+    }
+    Ok(use_recovery_code(db, user.id, code).await?)
+}
+
+/// Replaces the recovery codes of `user_id` with new ones and returns them. Only their hashes
+/// are stored.
+pub async fn issue_recovery_codes<C: ConnectionTrait>(db: &C, user_id: i32) -> Result<Vec<String>, DbErr> {
+    MfaRecoveryCode::delete_many().filter(Column::UserId.eq(user_id)).exec(db).await?;
+
+    let now = Utc::now().fixed_offset();
+    let codes: Vec<String> = (0..RECOVERY_CODES).map(|_| recovery_code()).collect();
+    let models = codes.iter().map(|code| ActiveModel {
+        id: NotSet,
+        user_id: Set(user_id),
+        code_hash: Set(hash_token(&normalize(code))),
+        used_at: Set(None),
+        created_at: Set(now),
+    });
+    MfaRecoveryCode::insert_many(models).exec(db).await?;
+
+    Ok(codes)
+}
+
+// a code is only accepted for a later time step than the last accepted one, which the update
+// checks and moves in one statement, so it can't be replayed by concurrent requests either
+async fn check_code<C: ConnectionTrait>(db: &C, user: &UserModel, secret: &str, code: &str) -> Result<bool, AppError> {
+    let totp = totp(user, secret)?;
+    let current = Utc::now().timestamp().max(0) as u64 / STEP;
+    let step = (current.saturating_sub(SKEW)..=current + SKEW)
+        .find(|step| bool::from(totp.generate(step * STEP).as_bytes().ct_eq(code.as_bytes())));
+    let Some(step) = step else {
+        return Ok(false);
+    };
+
+    let step = step as i64;
+    let accepted = User::update_many()
+        .col_expr(UserColumn::MfaLastStep, Expr::value(step))
+        .filter(UserColumn::Id.eq(user.id))
+        .filter(Condition::any().add(UserColumn::MfaLastStep.is_null()).add(UserColumn::MfaLastStep.lt(step)))
+        .exec(db)
+        .await?;
+    Ok(accepted.rows_affected == 1)
+}
+
+async fn use_recovery_code<C: ConnectionTrait>(db: &C, user_id: i32, code: &str) -> Result<bool, DbErr> {
+    let used = MfaRecoveryCode::update_many()
+        .col_expr(Column::UsedAt, Expr::value(Utc::now().fixed_offset()))
+        .filter(Column::UserId.eq(user_id))
+        .filter(Column::CodeHash.eq(hash_token(&normalize(code))))
+        .filter(Column::UsedAt.is_null())
+        .exec(db)
+        .await?;
+    Ok(used.rows_affected > 0)
+}
+
+fn totp(user: &UserModel, secret: &str) -> Result<TOTP, AppError> {
+    let bytes = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|err| AppError::Internal(format!("invalid TOTP secret of user {}: {}", user.id, err)))?;
+    let account = user.username.clone().unwrap_or_default();
+
+    TOTP::new(Algorithm::SHA1, DIGITS, SKEW as u8, STEP, bytes, Some(get_mfa_issuer()), account)
+        .map_err(|err| AppError::Internal(format!("invalid TOTP parameters: {}", err)))
+}
+
+// ten characters in two groups, like `k3x9q-7m2pd`
+fn recovery_code() -> String {
+    let characters: String = (0..10)
+        .map(|_| RECOVERY_ALPHABET[OsRng.next_u32() as usize % RECOVERY_ALPHABET.len()] as char)
+        .collect();
+    format!("{}-{}", &characters[..5], &characters[5..])
+}
+
+// recovery codes are compared without case, dashes or spaces
+fn normalize(code: &str) -> String {
+    code.chars().filter(char::is_ascii_alphanumeric).collect::<String>().to_lowercase()
+}
@@ -1,7 +1,13 @@
 // private modules
+mod email_verification;
+mod invitations;
+mod logins;
+mod mfa;
 mod models;
+mod password_reset;
+mod search;
 mod serializers;
-mod pagination;
+mod validators;
 
 // public modules
 pub mod handlers;
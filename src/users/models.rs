@@ -1,5 +1,13 @@
-use chrono::NaiveDateTime;
+use crate::auth::guards::CurrentUser;
+use crate::users::invitations::Role;
+use crate::users::search::SearchHit;
+use crate::users::validators::{validate_password, USERNAME_REGEX};
+use chrono::Utc;
+use sea_orm::prelude::DateTimeWithTimeZone;
+use entity::{invitation, login_event};
+use entity::user::{Column, Model};
 use serde::{Deserialize, Serialize};
+use validator::Validate;
 
 
 #[derive(Serialize)]
@@ -8,18 +16,478 @@ pub struct LoginResponse {
     pub refresh_token: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct UserRequest {
+/// The answer to a right password when a second factor is needed. `mfa_token` goes to
+/// `/auth/mfa/verify` with a code, or to `/auth/mfa/enroll` first when `enrollment_required`.
+#[derive(Serialize)]
+pub struct MfaChallenge {
+    pub mfa_token: String,
+    pub enrollment_required: bool,
+}
+
+#[derive(Serialize)]
+pub struct MfaLoginResponse {
+    #[serde(flatten)]
+    pub tokens: LoginResponse,
+    /// only when this login turned two-factor authentication on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_codes: Option<Vec<String>>,
+}
+
+/// A new secret for the authenticator app. It's shown once, the server only ever checks codes.
+#[derive(Serialize)]
+pub struct MfaSetupResponse {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Serialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct MfaTokenRequest {
+    #[validate(length(min = 1, message = "is required"))]
+    pub mfa_token: String,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyMfa {
+    #[validate(length(min = 1, message = "is required"))]
+    pub mfa_token: String,
+    /// a code from the authenticator app or a recovery code
+    #[validate(length(min = 1, message = "is required"))]
+    pub code: String,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct MfaCode {
+    #[validate(length(min = 1, message = "is required"))]
+    pub code: String,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct LoginRequest {
+    /// a username or an email address, in any case
+    #[serde(alias = "username")]
+    #[validate(length(min = 1, message = "is required"))]
+    pub identifier: String,
+    #[validate(length(min = 1, message = "is required"))]
+    pub password: String,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct ForgotPassword {
+    #[validate(email(message = "must be a valid email address"))]
+    pub email: String,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct ResetPassword {
+    #[validate(length(min = 1, message = "is required"))]
+    pub token: String,
+    #[validate(custom(function = "validate_password"))]
+    pub password: String,
+}
+
+// `id`, `created_at`, `updated_at`, `date_joined` and `last_login` are managed by the server,
+// `deny_unknown_fields` rejects requests that try to set them
+
+#[derive(Deserialize, Debug, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct CreateUser {
+    #[validate(length(min = 3, max = 32, message = "must be between 3 and 32 characters long"))]
+    #[validate(regex(path = *USERNAME_REGEX, message = "may only contain letters, digits, `_`, `.` and `-`"))]
+    pub username: String,
+    #[validate(length(max = 150, message = "must be at most 150 characters long"))]
+    pub firstname: Option<String>,
+    #[validate(length(max = 150, message = "must be at most 150 characters long"))]
+    pub lastname: Option<String>,
+    #[validate(email(message = "must be a valid email address"))]
+    pub email: String,
+    #[validate(custom(function = "validate_password"))]
+    pub password: String,
+    pub is_active: Option<bool>,
+    pub is_admin: Option<bool>,
+    pub is_superadmin: Option<bool>,
+}
+
+/// Self-registration. Unlike `CreateUser` there are no flags, the server decides them.
+#[derive(Deserialize, Debug, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct RegisterUser {
+    #[validate(length(min = 3, max = 32, message = "must be between 3 and 32 characters long"))]
+    #[validate(regex(path = *USERNAME_REGEX, message = "may only contain letters, digits, `_`, `.` and `-`"))]
+    pub username: String,
+    #[validate(length(max = 150, message = "must be at most 150 characters long"))]
+    pub firstname: Option<String>,
+    #[validate(length(max = 150, message = "must be at most 150 characters long"))]
+    pub lastname: Option<String>,
+    #[validate(email(message = "must be a valid email address"))]
+    pub email: String,
+    #[validate(custom(function = "validate_password"))]
+    pub password: String,
+}
+
+impl RegisterUser {
+    pub fn into_create_user(self, is_active: bool) -> CreateUser {
+        CreateUser {
+            username: self.username,
+            firstname: self.firstname,
+            lastname: self.lastname,
+            email: self.email,
+            password: self.password,
+            is_active: Some(is_active),
+            is_admin: None,
+            is_superadmin: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct CreateInvitation {
+    #[validate(email(message = "must be a valid email address"))]
+    pub email: String,
+    #[serde(default)]
+    pub role: Role,
+}
+
+/// Accepting an invitation. The email address and role come from the invitation.
+#[derive(Deserialize, Debug, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct AcceptInvitation {
+    #[validate(length(min = 1, message = "is required"))]
+    pub token: String,
+    #[validate(length(min = 3, max = 32, message = "must be between 3 and 32 characters long"))]
+    #[validate(regex(path = *USERNAME_REGEX, message = "may only contain letters, digits, `_`, `.` and `-`"))]
+    pub username: String,
+    #[validate(length(max = 150, message = "must be at most 150 characters long"))]
+    pub firstname: Option<String>,
+    #[validate(length(max = 150, message = "must be at most 150 characters long"))]
+    pub lastname: Option<String>,
+    #[validate(custom(function = "validate_password"))]
+    pub password: String,
+}
+
+impl AcceptInvitation {
+    /// The invitation link was sent to `email`, so the account is active right away.
+    pub fn into_create_user(self, email: String, role: Role) -> CreateUser {
+        CreateUser {
+            username: self.username,
+            firstname: self.firstname,
+            lastname: self.lastname,
+            email,
+            password: self.password,
+            is_active: Some(true),
+            is_admin: Some(role == Role::Admin),
+            is_superadmin: Some(role == Role::Superadmin),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VerifyEmailQuery {
+    pub token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeleteQuery {
+    pub purge: Option<bool>,
+}
+
+/// Partial update, absent fields are left untouched.
+#[derive(Deserialize, Debug, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct PatchUser {
+    #[validate(length(min = 3, max = 32, message = "must be between 3 and 32 characters long"))]
+    #[validate(regex(path = *USERNAME_REGEX, message = "may only contain letters, digits, `_`, `.` and `-`"))]
     pub username: Option<String>,
+    #[validate(length(max = 150, message = "must be at most 150 characters long"))]
     pub firstname: Option<String>,
+    #[validate(length(max = 150, message = "must be at most 150 characters long"))]
     pub lastname: Option<String>,
+    #[validate(email(message = "must be a valid email address"))]
     pub email: Option<String>,
+    #[validate(custom(function = "validate_password"))]
     pub password: Option<String>,
     pub is_active: Option<bool>,
-    pub last_login: Option<NaiveDateTime>,
-    pub date_joined: Option<NaiveDateTime>,
-    pub created_at: Option<NaiveDateTime>,
-    pub updated_at: Option<NaiveDateTime>,
     pub is_admin: Option<bool>,
     pub is_superadmin: Option<bool>,
-}
\ No newline at end of file
+}
+
+/// Full replacement of a user. Absent names are cleared, and the password is only changed
+/// when one is given.
+#[derive(Deserialize, Serialize, Debug, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct ReplaceUser {
+    #[validate(length(min = 3, max = 32, message = "must be between 3 and 32 characters long"))]
+    #[validate(regex(path = *USERNAME_REGEX, message = "may only contain letters, digits, `_`, `.` and `-`"))]
+    pub username: String,
+    #[validate(length(max = 150, message = "must be at most 150 characters long"))]
+    pub firstname: Option<String>,
+    #[validate(length(max = 150, message = "must be at most 150 characters long"))]
+    pub lastname: Option<String>,
+    #[validate(email(message = "must be a valid email address"))]
+    pub email: String,
+    #[validate(custom(function = "validate_password"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    pub is_active: bool,
+    pub is_admin: bool,
+    pub is_superadmin: bool,
+}
+
+impl ReplaceUser {
+    /// The current state of `user` as a document that JSON Merge Patch and JSON Patch requests
+    /// are applied to. The password is write-only, so it's left out.
+    pub fn from_model(user: &Model) -> Self {
+        ReplaceUser {
+            username: user.username.clone().unwrap_or_default(),
+            firstname: user.firstname.clone(),
+            lastname: user.lastname.clone(),
+            email: user.email.clone().unwrap_or_default(),
+            password: None,
+            is_active: user.is_active.unwrap_or(false),
+            is_admin: user.is_admin.unwrap_or(false),
+            is_superadmin: user.is_superadmin.unwrap_or(false),
+        }
+    }
+}
+
+/// How much of a user the caller gets to see.
+#[derive(Clone, Copy, PartialEq)]
+pub enum UserView {
+    /// anonymous callers and other users: names only
+    Public,
+    /// the user themself: also contact details, account timestamps and two-factor settings
+    Owner,
+    /// admins: everything but the password
+    Admin,
+}
+
+const PUBLIC_FIELDS: &[(&str, Column)] = &[
+    ("id", Column::Id),
+    ("username", Column::Username),
+    ("firstname", Column::Firstname),
+    ("lastname", Column::Lastname),
+    ("date_joined", Column::DateJoined),
+];
+
+const OWNER_FIELDS: &[(&str, Column)] = &[
+    ("id", Column::Id),
+    ("username", Column::Username),
+    ("firstname", Column::Firstname),
+    ("lastname", Column::Lastname),
+    ("date_joined", Column::DateJoined),
+    ("email", Column::Email),
+    ("is_active", Column::IsActive),
+    ("last_login", Column::LastLogin),
+    ("created_at", Column::CreatedAt),
+    ("updated_at", Column::UpdatedAt),
+    ("mfa_enabled_at", Column::MfaEnabledAt),
+    ("mfa_required", Column::MfaRequired),
+    ("pending_email", Column::PendingEmail),
+];
+
+const ADMIN_FIELDS: &[(&str, Column)] = &[
+    ("id", Column::Id),
+    ("username", Column::Username),
+    ("firstname", Column::Firstname),
+    ("lastname", Column::Lastname),
+    ("date_joined", Column::DateJoined),
+    ("email", Column::Email),
+    ("is_active", Column::IsActive),
+    ("last_login", Column::LastLogin),
+    ("created_at", Column::CreatedAt),
+    ("updated_at", Column::UpdatedAt),
+    ("is_admin", Column::IsAdmin),
+    ("is_superadmin", Column::IsSuperadmin),
+    ("mfa_enabled_at", Column::MfaEnabledAt),
+    ("mfa_required", Column::MfaRequired),
+    ("pending_email", Column::PendingEmail),
+];
+
+impl UserView {
+    /// The fields of `UserResponse` this view shows, with their columns. These are the only
+    /// fields callers can filter, sort or select by, so `password` is never among them.
+    pub fn fields(&self) -> &'static [(&'static str, Column)] {
+        match self {
+            UserView::Public => PUBLIC_FIELDS,
+            UserView::Owner => OWNER_FIELDS,
+            UserView::Admin => ADMIN_FIELDS,
+        }
+    }
+
+    pub fn for_viewer(viewer: Option<&CurrentUser>, user_id: i32) -> Self {
+        match viewer {
+            Some(viewer) if viewer.is_admin || viewer.is_superadmin => UserView::Admin,
+            Some(viewer) if viewer.id == user_id => UserView::Owner,
+            _ => UserView::Public,
+        }
+    }
+}
+
+/// What the API returns for a user. There is deliberately no password field.
+#[derive(Serialize, Debug)]
+pub struct UserResponse {
+    pub id: i32,
+    pub username: Option<String>,
+    pub firstname: Option<String>,
+    pub lastname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_active: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_login: Option<DateTimeWithTimeZone>,
+    pub date_joined: Option<DateTimeWithTimeZone>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTimeWithTimeZone>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTimeWithTimeZone>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_admin: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_superadmin: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mfa_enabled_at: Option<DateTimeWithTimeZone>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mfa_required: Option<bool>,
+    /// an address the user changed to that waits for verification
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pending_email: Option<String>,
+}
+
+impl UserResponse {
+    pub fn new(user: Model, view: UserView) -> Self {
+        let private = view != UserView::Public;
+        let admin = view == UserView::Admin;
+
+        UserResponse {
+            id: user.id,
+            username: user.username,
+            firstname: user.firstname,
+            lastname: user.lastname,
+            email: user.email.filter(|_| private),
+            is_active: user.is_active.filter(|_| private),
+            last_login: user.last_login.filter(|_| private),
+            date_joined: user.date_joined,
+            created_at: user.created_at.filter(|_| private),
+            updated_at: user.updated_at.filter(|_| private),
+            is_admin: user.is_admin.filter(|_| admin),
+            is_superadmin: user.is_superadmin.filter(|_| admin),
+            mfa_enabled_at: user.mfa_enabled_at.filter(|_| private),
+            mfa_required: user.mfa_required.filter(|_| private),
+            pending_email: user.pending_email.filter(|_| private),
+        }
+    }
+
+    pub fn for_viewer(user: Model, viewer: Option<&CurrentUser>) -> Self {
+        let view = UserView::for_viewer(viewer, user.id);
+        UserResponse::new(user, view)
+    }
+}
+
+/// One `/users/search` result: the user as the viewer may see them, how well they matched and
+/// the matching parts of each field wrapped in `<mark>` tags, with the rest HTML-escaped.
+#[derive(Serialize, Debug)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    pub user: UserResponse,
+    pub rank: f32,
+    pub highlight: Highlight,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Highlight {
+    pub username: Option<String>,
+    pub firstname: Option<String>,
+    pub lastname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+impl SearchResult {
+    pub fn for_viewer(hit: SearchHit, viewer: Option<&CurrentUser>) -> Self {
+        let user = UserResponse::for_viewer(hit.user, viewer);
+        let highlight = Highlight {
+            username: hit.username_highlight,
+            firstname: hit.firstname_highlight,
+            lastname: hit.lastname_highlight,
+            email: hit.email_highlight.filter(|_| user.email.is_some()),
+        };
+        SearchResult { user, rank: hit.rank, highlight }
+    }
+}
+
+/// One entry of a user's login history.
+#[derive(Serialize, Debug)]
+pub struct LoginEventResponse {
+    pub id: i64,
+    pub outcome: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+impl From<login_event::Model> for LoginEventResponse {
+    fn from(event: login_event::Model) -> Self {
+        LoginEventResponse {
+            id: event.id,
+            outcome: event.outcome,
+            ip: event.ip,
+            user_agent: event.user_agent,
+            created_at: event.created_at,
+        }
+    }
+}
+
+/// An invitation as admins see it. The token is only ever in the email.
+#[derive(Serialize, Debug)]
+pub struct InvitationResponse {
+    pub id: i32,
+    pub email: String,
+    pub role: String,
+    /// `pending`, `accepted`, `revoked` or `expired`
+    pub status: &'static str,
+    pub invited_by: Option<i32>,
+    pub accepted_by: Option<i32>,
+    pub expires_at: DateTimeWithTimeZone,
+    pub accepted_at: Option<DateTimeWithTimeZone>,
+    pub revoked_at: Option<DateTimeWithTimeZone>,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+impl From<invitation::Model> for InvitationResponse {
+    fn from(invitation: invitation::Model) -> Self {
+        let status = if invitation.accepted_at.is_some() {
+            "accepted"
+        } else if invitation.revoked_at.is_some() {
+            "revoked"
+        } else if invitation.expires_at <= Utc::now() {
+            "expired"
+        } else {
+            "pending"
+        };
+
+        InvitationResponse {
+            id: invitation.id,
+            email: invitation.email,
+            role: invitation.role,
+            status,
+            invited_by: invitation.invited_by,
+            accepted_by: invitation.accepted_by,
+            expires_at: invitation.expires_at,
+            accepted_at: invitation.accepted_at,
+            revoked_at: invitation.revoked_at,
+            created_at: invitation.created_at,
+        }
+    }
+}
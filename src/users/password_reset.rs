@@ -0,0 +1,94 @@
+use crate::utils::config::{get_password_reset_ttl, get_password_reset_url};
+use crate::utils::mail::{link, Email};
+use crate::utils::secret::{hash_token, random_token};
+use chrono::{TimeDelta, Utc};
+use entity::password_reset_token::{ActiveModel, Column, Entity as PasswordResetToken};
+use entity::user::Model as UserModel;
+use sea_orm::sea_query::Expr;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+
+// a new link is only sent when the last one is at least this old, so the endpoint can't be
+// used to flood someone's inbox
+const RESEND_INTERVAL: TimeDelta = TimeDelta::minutes(1);
+
+/// Creates a reset token for `user_id` and returns it, replacing any unused ones. Only its
+/// hash is stored. Returns `None` when a token was issued less than a minute ago.
+pub async fn issue(db: &DatabaseConnection, user_id: i32) -> Result<Option<String>, DbErr> {
+    let now = Utc::now().fixed_offset();
+    let recent = PasswordResetToken::find()
+        .filter(Column::UserId.eq(user_id))
+        .filter(Column::UsedAt.is_null())
+        .filter(Column::CreatedAt.gt(now - RESEND_INTERVAL))
+        .one(db)
+        .await?;
+    if recent.is_some() {
+        return Ok(None);
+    }
+
+    PasswordResetToken::delete_many()
+        .filter(Column::UserId.eq(user_id))
+        .filter(Column::UsedAt.is_null())
+        .exec(db)
+        .await?;
+
+    let token = random_token();
+    let reset_token = ActiveModel {
+        token_hash: Set(hash_token(&token)),
+        user_id: Set(user_id),
+        expires_at: Set(now + TimeDelta::from_std(get_password_reset_ttl()).unwrap_or_default()),
+        used_at: Set(None),
+        created_at: Set(now),
+    };
+    PasswordResetToken::insert(reset_token).exec(db).await?;
+
+    Ok(Some(token))
+}
+
+/// Marks `token` as used and returns the user it was issued to, unless it is unknown, used or
+/// expired. Marking and checking happen in one statement, so a token can't be used twice by
+/// concurrent requests.
+pub async fn consume(db: &DatabaseConnection, token: &str) -> Result<Option<i32>, DbErr> {
+    let now = Utc::now().fixed_offset();
+    let consumed = PasswordResetToken::update_many()
+        .col_expr(Column::UsedAt, Expr::value(now))
+        .filter(Column::TokenHash.eq(hash_token(token)))
+        .filter(Column::UsedAt.is_null())
+        .filter(Column::ExpiresAt.gt(now))
+        .exec_with_returning(db)
+        .await?;
+    let Some(user_id) = consumed.first().map(|reset_token| reset_token.user_id) else {
+        return Ok(None);
+    };
+
+    // other links of the user are void now, and expired ones of anyone are of no use
+    PasswordResetToken::delete_many()
+        .filter(
+            Column::UserId.eq(user_id).and(Column::UsedAt.is_null())
+                .or(Column::ExpiresAt.lt(now)),
+        )
+        .exec(db)
+        .await?;
+
+    Ok(Some(user_id))
+}
+
+/// The email with the reset link for `user`.
+pub fn email(user: &UserModel, token: &str) -> Email {
+    let link = link(&get_password_reset_url(), token);
+    let minutes = get_password_reset_ttl().as_secs() / 60;
+
+    Email {
+        to: user.email.clone().unwrap_or_default(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hi {},\n\n\
+            someone asked to reset the password of your account. To choose a new one, open this \
+            link within {} minutes:\n\n{}\n\n\
+            If that wasn't you, you can ignore this email and your password stays the same.\n",
+            user.username.as_deref().unwrap_or_default(),
+            minutes,
+            link,
+        ),
+    }
+}
@@ -0,0 +1,67 @@
+use entity::user::{Entity as User, Model};
+use sea_orm::sea_query::{Expr, SimpleExpr};
+use sea_orm::{FromQueryResult, QueryFilter, QueryOrder, QuerySelect, Select};
+
+const HEADLINE_OPTIONS: &str = "StartSel=<mark>, StopSel=</mark>, HighlightAll=true";
+
+/// A user matched by `search`, with its relevance and the matching parts of each field wrapped
+/// in `<mark>` tags. The highlights are HTML-escaped apart from those tags, so they can be put
+/// into a page as they are.
+#[derive(FromQueryResult)]
+pub struct SearchHit {
+    #[sea_orm(nested)]
+    pub user: Model,
+    pub rank: f32,
+    pub username_highlight: Option<String>,
+    pub firstname_highlight: Option<String>,
+    pub lastname_highlight: Option<String>,
+    pub email_highlight: Option<String>,
+}
+
+/// Users matching `q`, best match first. Every word of `q` must prefix-match a word of the
+/// username, names or email through the `search_vector` GIN index; the trigram index on
+/// `search_text` also catches misspellings. Returns `None` when `q` has nothing to search for.
+pub fn search(q: &str) -> Option<Select<User>> {
+    let tsquery = prefix_query(q)?;
+    let text = q.trim().to_lowercase();
+    let query = || Expr::cust_with_values("to_tsquery('simple', $1)", [tsquery.clone()]);
+    // `ts_headline` returns its input as it is, so markup in a name would reach the page
+    let headline = |column: &str| {
+        Expr::cust_with_exprs(
+            format!(
+                "ts_headline('simple', replace(replace(replace(\"{}\", '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), $1, $2)",
+                column,
+            ),
+            [query(), Expr::val(HEADLINE_OPTIONS).into()],
+        )
+    };
+
+    let matches = Expr::cust_with_exprs("search_vector @@ $1", [query()])
+        .or(Expr::cust_with_values("$1 <% search_text", [text.clone()]));
+    let rank = Expr::cust_with_exprs(
+        "ts_rank(search_vector, $1) + word_similarity($2, search_text)",
+        [query(), SimpleExpr::from(Expr::val(text))],
+    );
+
+    let select = User::find_existing()
+        .filter(matches)
+        .column_as(rank.clone(), "rank")
+        .column_as(headline("username"), "username_highlight")
+        .column_as(headline("firstname"), "firstname_highlight")
+        .column_as(headline("lastname"), "lastname_highlight")
+        .column_as(headline("email"), "email_highlight")
+        .order_by_desc(rank)
+        .order_by_asc(entity::user::Column::Id);
+    Some(select)
+}
+
+/// Turns free text into a `tsquery` like `ann:* & smi:*`. Only letters and digits are kept, so
+/// nothing in `q` is interpreted as `tsquery` syntax.
+fn prefix_query(q: &str) -> Option<String> {
+    let terms: Vec<String> = q
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("{}:*", term.to_lowercase()))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" & "))
+}
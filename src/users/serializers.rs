@@ -1,55 +1,42 @@
-use crate::users::models::UserRequest;
-use actix_web::web::Json;
+use crate::users::models::CreateUser;
+use crate::utils::password::PasswordHasher;
+use argon2::password_hash::Error;
 use entity::user::ActiveModel as User;
 use sea_orm::ActiveValue;
 
 pub struct UserSerializer {
-    pub data: Json<UserRequest>,
+    pub data: CreateUser,
 }
 
 impl UserSerializer {
-    pub fn serialize(&self) -> User {
-        let is_active = self.is_active();
-        let is_admin = self.is_admin();
-        let is_superadmin = self.is_superadmin();
+    pub fn serialize(&self) -> Result<User, Error> {
+        let password = PasswordHasher::new().hash(&self.data.password)?;
 
         let user = User {
-            username: ActiveValue::Set(self.data.username.clone()),
+            username: ActiveValue::Set(Some(self.data.username.clone())),
             firstname: ActiveValue::Set(self.data.firstname.clone()),
             lastname: ActiveValue::Set(self.data.lastname.clone()),
-            email: ActiveValue::Set(self.data.email.clone()),
-            password: ActiveValue::Set(self.data.password.clone()),
-            is_active: ActiveValue::Set(Option::from(is_active)),
-            last_login: ActiveValue::Set(Option::from(self.data.last_login)),
-            date_joined: ActiveValue::Set(Option::from(self.data.date_joined)),
-            created_at: ActiveValue::Set(Option::from(self.data.created_at)),
-            updated_at: ActiveValue::Set(Option::from(self.data.updated_at)),
-            is_admin: ActiveValue::Set(Option::from(is_admin)),
-            is_superadmin: ActiveValue::Set(Option::from(is_superadmin)),
+            email: ActiveValue::Set(Some(self.data.email.clone())),
+            password: ActiveValue::Set(Some(password)),
+            is_active: ActiveValue::Set(Some(self.is_active())),
+            last_login: ActiveValue::Set(None),
+            is_admin: ActiveValue::Set(Some(self.is_admin())),
+            is_superadmin: ActiveValue::Set(Some(self.is_superadmin())),
             ..Default::default()
         };
 
-        user
+        Ok(user)
     }
 
     fn is_active(&self) -> bool {
-        match self.data.is_active {
-            None => false,
-            Some(val) => { if val == true { true } else { false } }
-        }
+        self.data.is_active.unwrap_or(false)
     }
 
     fn is_admin(&self) -> bool {
-        match self.data.is_admin {
-            None => false,
-            Some(val) => { if val == true { true } else { false } }
-        }
+        self.data.is_admin.unwrap_or(false)
     }
 
     fn is_superadmin(&self) -> bool {
-        match self.data.is_admin {
-            None => false,
-            Some(val) => { if val == true { true } else { false } }
-        }
+        self.data.is_superadmin.unwrap_or(false)
     }
-}
\ No newline at end of file
+}
@@ -1,7 +1,7 @@
 use actix_web::middleware::from_fn;
 use actix_web::web;
 use crate::users::handlers;
-use crate::auth::middlewares::authenticate;
+use crate::auth::middlewares::{authenticate, identify};
 
 pub fn routes(config: &mut web::ServiceConfig) {
     config
@@ -12,11 +12,45 @@ pub fn routes(config: &mut web::ServiceConfig) {
                 .service(handlers::update_user)
                 .service(handlers::update_user_full)
                 .service(handlers::delete_user)
+                .service(handlers::restore_user)
+                .service(handlers::get_user_logins)
+                .service(handlers::unlock_user)
+                .service(handlers::resend_verification_email)
+                .service(handlers::require_mfa)
+                .service(handlers::unrequire_mfa)
+                .service(handlers::reset_mfa)
         )
+        .service(handlers::accept_invitation)
+        .service(
+            web::scope("/auth/invitations")
+                .wrap(from_fn(authenticate))
+                .service(handlers::create_invitation)
+                .service(handlers::get_invitations)
+                .service(handlers::revoke_invitation)
+        )
+        .service(handlers::register)
+        .service(handlers::verify_email)
+        .service(
+            web::scope("/auth/mfa")
+                .service(handlers::verify_mfa)
+                .service(handlers::enroll_mfa)
+                .service(handlers::setup_mfa)
+                .service(handlers::enable_mfa)
+                .service(handlers::disable_mfa)
+                .service(handlers::regenerate_recovery_codes)
+        )
+        .service(
+            web::scope("/auth/password")
+                .service(handlers::forgot_password)
+                .service(handlers::reset_password)
+        )
+        // outside of the `identify` scope, a stale token must not get in the way of logging in
+        .service(handlers::login)
         .service(
             web::scope("/users")
+                .wrap(from_fn(identify))
                 .service(handlers::get_users)
+                .service(handlers::search_users)
                 .service(handlers::get_user)
-                .service(handlers::login)
         );
 }
\ No newline at end of file
@@ -0,0 +1,60 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::borrow::Cow;
+use validator::ValidationError;
+
+lazy_static! {
+    pub static ref USERNAME_REGEX: Regex = Regex::new(r"^[A-Za-z0-9_.-]+$").unwrap();
+}
+
+const PASSWORD_MIN_LENGTH: usize = 8;
+const PASSWORD_MAX_LENGTH: usize = 128;
+
+pub fn validate_password(password: &str) -> Result<(), ValidationError> {
+    let length = password.chars().count();
+    if !(PASSWORD_MIN_LENGTH..=PASSWORD_MAX_LENGTH).contains(&length) {
+        return Err(password_error("length", format!(
+            "must be between {} and {} characters long", PASSWORD_MIN_LENGTH, PASSWORD_MAX_LENGTH
+        )));
+    }
+    if !password.chars().any(char::is_alphabetic) || !password.chars().any(|c| c.is_ascii_digit()) {
+        return Err(password_error("complexity", "must contain at least one letter and one digit".to_string()));
+    }
+
+    Ok(())
+}
+
+fn password_error(code: &'static str, message: String) -> ValidationError {
+    ValidationError::new(code).with_message(Cow::Owned(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_code(password: &str) -> Option<String> {
+        validate_password(password).err().map(|err| err.code.to_string())
+    }
+
+    #[test]
+    fn accepts_passwords_with_letters_and_digits() {
+        assert_eq!(error_code("hunter22"), None);
+        assert_eq!(error_code("pässwört1"), None);
+    }
+
+    #[test]
+    fn checks_the_length_in_characters() {
+        assert_eq!(error_code("abcdef1"), Some("length".to_string()));
+        assert_eq!(error_code(&format!("{}1", "a".repeat(PASSWORD_MAX_LENGTH - 1))), None);
+        assert_eq!(error_code(&format!("{}1", "a".repeat(PASSWORD_MAX_LENGTH))), Some("length".to_string()));
+        // 8 characters, but 16 bytes
+        assert_eq!(error_code("äääääää1"), None);
+    }
+
+    #[test]
+    fn requires_a_letter_and_a_digit() {
+        assert_eq!(error_code("12345678"), Some("complexity".to_string()));
+        assert_eq!(error_code("abcdefgh"), Some("complexity".to_string()));
+        assert_eq!(error_code("abcdefg١"), Some("complexity".to_string()));
+    }
+}
@@ -1,5 +1,9 @@
+use crate::auth::revocation::RevocationList;
+use crate::utils::mail::Mailer;
 use sea_orm::DatabaseConnection;
 
 pub struct AppState {
     pub db: DatabaseConnection,
-}
\ No newline at end of file
+    pub revocations: RevocationList,
+    pub mailer: Box<dyn Mailer>,
+}
@@ -1,18 +1,56 @@
-use chrono::{Duration, Utc};
-use jsonwebtoken::errors::Error;
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::errors::{Error, ErrorKind};
+use crate::utils::keys::KeySet;
+use jsonwebtoken::{decode, decode_header, encode, Header, TokenData, Validation};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize)]
+pub const ACCESS_TOKEN_EXPIRY: Duration = Duration::hours(1);
+pub const REFRESH_TOKEN_EXPIRY: Duration = Duration::days(7);
+pub const EMAIL_VERIFICATION_EXPIRY: Duration = Duration::days(1);
+pub const MFA_PENDING_EXPIRY: Duration = Duration::minutes(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    Access,
+    Refresh,
+    /// sent in a link to a new user, see `users::email_verification`
+    #[serde(rename = "email_verification")]
+    EmailVerification,
+    /// proves the password of a user with two-factor authentication, see `users::mfa`
+    #[serde(rename = "mfa_pending")]
+    MfaPending,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub(crate) exp: i64,
-    pub(crate) iat: i64,
+    /// seconds with a fraction, so tokens issued right after a `revoke_all` in the same second
+    /// are still told apart from the ones before it
+    pub(crate) iat: f64,
+    pub(crate) jti: Uuid,
+    pub(crate) typ: TokenType,
     pub(crate) id: i32,
     pub(crate) email: String,
+    /// token family, see `auth::tokens`. Access tokens carry it too so logout can end the session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) fam: Option<Uuid>,
+}
+
+impl Claims {
+    /// When the token was issued, in microseconds since the epoch.
+    pub fn issued_at_micros(&self) -> i64 {
+        (self.iat * 1e6).round() as i64
+    }
+}
+
+fn issued_at(now: DateTime<Utc>) -> f64 {
+    now.timestamp_micros() as f64 / 1e6
 }
 
 pub struct JSONWebToken {
-    pub(crate) secret: String,
+    pub(crate) keys: &'static KeySet,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -22,35 +60,80 @@ pub struct Token {
 }
 
 impl JSONWebToken {
-    pub fn decode(&self, jwt: String) -> Result<TokenData<Claims>, Error> {
-        let token_data: Result<TokenData<Claims>, Error> = decode::<Claims>(
-            &jwt,
-            &DecodingKey::from_secret(self.secret.as_bytes()),
-            &Validation::new(Algorithm::HS512),
-        );
-
-        token_data
+    /// Decodes `jwt` and makes sure it is a token of the `expected` type, so a refresh token
+    /// can't be used as a bearer token and vice versa.
+    pub fn decode(&self, jwt: String, expected: TokenType) -> Result<TokenData<Claims>, Error> {
+        // the `kid` header picks the key, and the key decides which algorithm is acceptable
+        let header = decode_header(&jwt)?;
+        let key = self.keys.verification.get(&header.kid).ok_or(Error::from(ErrorKind::InvalidKeyFormat))?;
+        let token_data = decode::<Claims>(&jwt, &key.key, &Validation::new(key.algorithm))?;
+
+        if token_data.claims.typ != expected {
+            return Err(ErrorKind::InvalidToken.into());
+        }
+
+        Ok(token_data)
     }
 
-    pub fn encode(&self, id: i32, email: String) -> Token {
+    /// Signs a new access/refresh pair. The refresh token carries `family` and `refresh_jti`,
+    /// which have to be recorded by the caller for rotation to work.
+    pub fn encode(&self, id: i32, email: String, family: Uuid, refresh_jti: Uuid) -> Token {
         let now = Utc::now();
-        let expiry = Duration::hours(1);
+        let mut header = Header::new(self.keys.signing.algorithm);
+        header.kid = self.keys.signing.kid.clone();
+        let encoding_key = &self.keys.signing.key;
 
-        let mut claims = Claims {
-            exp: (now + expiry).timestamp(),
-            iat: now.timestamp(),
+        let access_claims = Claims {
+            exp: (now + ACCESS_TOKEN_EXPIRY).timestamp(),
+            iat: issued_at(now),
+            jti: Uuid::new_v4(),
+            typ: TokenType::Access,
+            id,
+            email: email.clone(),
+            fam: Some(family),
+        };
+        let token = encode(&header, &access_claims, encoding_key).unwrap_or_else(|err| err.to_string());
+
+        let refresh_claims = Claims {
+            exp: (now + REFRESH_TOKEN_EXPIRY).timestamp(),
+            iat: issued_at(now),
+            jti: refresh_jti,
+            typ: TokenType::Refresh,
             id,
             email,
+            fam: Some(family),
         };
+        let refresh_token = encode(&header, &refresh_claims, encoding_key).unwrap_or_else(|err| err.to_string());
 
-        let header = Header::new(Algorithm::HS512);
-        let encoding_key = EncodingKey::from_secret(self.secret.as_bytes());
-        let token = encode(&header, &claims, &encoding_key).unwrap_or_else(|err| err.to_string());
+        Token { token, refresh_token }
+    }
 
-        let refresh_expiry = Duration::days(7);
-        claims.exp = (now + refresh_expiry).timestamp();
-        let refresh_token = encode(&header, &claims, &encoding_key).unwrap_or_else(|err| err.to_string());
+    /// Signs a token that proves `email` belongs to user `id`.
+    pub fn encode_email_verification(&self, id: i32, email: String) -> String {
+        self.encode_single(TokenType::EmailVerification, EMAIL_VERIFICATION_EXPIRY, id, email)
+    }
 
-        Token { token, refresh_token }
+    /// Signs a token that proves user `id` got the password right, to be exchanged for a
+    /// token pair once the second factor is checked too.
+    pub fn encode_mfa_pending(&self, id: i32, email: String) -> String {
+        self.encode_single(TokenType::MfaPending, MFA_PENDING_EXPIRY, id, email)
+    }
+
+    // a token outside of any family
+    fn encode_single(&self, typ: TokenType, expiry: Duration, id: i32, email: String) -> String {
+        let now = Utc::now();
+        let mut header = Header::new(self.keys.signing.algorithm);
+        header.kid = self.keys.signing.kid.clone();
+
+        let claims = Claims {
+            exp: (now + expiry).timestamp(),
+            iat: issued_at(now),
+            jti: Uuid::new_v4(),
+            typ,
+            id,
+            email,
+            fam: None,
+        };
+        encode(&header, &claims, &self.keys.signing.key).unwrap_or_else(|err| err.to_string())
     }
 }
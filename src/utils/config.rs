@@ -1,6 +1,10 @@
 use std::env;
 use std::env::VarError;
+use std::net::IpAddr;
 use std::time::Duration;
+use argon2::Params;
+use crate::utils::keys::KeySet;
+use crate::utils::mail::{FileMailer, Mailer, MemoryMailer, SmtpMailer};
 use lazy_static::lazy_static;
 use sea_orm::{ConnectOptions, Database, DatabaseConnection};
 
@@ -9,11 +13,44 @@ lazy_static! {
     pub static ref PORT: u16 = set_port();
     pub static ref DATABASE_URL: String = set_db();
     pub static ref SECRET: String = set_secret();
+    pub static ref PASSWORD_PARAMS: Params = set_password_params();
+    pub static ref REVOCATION_CACHE_TTL: u64 = set_revocation_cache_ttl();
+    pub static ref JWT_KEYS: KeySet = set_jwt_keys();
+    pub static ref DEFAULT_PAGE_SIZE: u64 = set_default_page_size();
+    pub static ref MAX_PAGE_SIZE: u64 = set_max_page_size();
+    pub static ref LOGIN_MAX_FAILURES: u32 = set_login_max_failures();
+    pub static ref LOGIN_MAX_FAILURES_PER_IP: u32 = set_login_max_failures_per_ip();
+    pub static ref LOGIN_BACKOFF_BASE: u64 = set_login_backoff_base();
+    pub static ref LOGIN_LOCKOUT_DURATION: u64 = set_login_lockout_duration();
+    pub static ref TRUSTED_PROXIES: Vec<IpAddr> = set_trusted_proxies();
+    pub static ref PASSWORD_RESET_URL: String = set_password_reset_url();
+    pub static ref PASSWORD_RESET_TTL: u64 = set_password_reset_ttl();
+    pub static ref EMAIL_VERIFICATION_URL: String = set_email_verification_url();
+    pub static ref EMAIL_VERIFICATION_REQUIRED: bool = set_email_verification_required();
+    pub static ref REGISTRATION_MODE: RegistrationMode = set_registration_mode();
+    pub static ref INVITATION_URL: String = set_invitation_url();
+    pub static ref INVITATION_TTL: u64 = set_invitation_ttl();
+    pub static ref MFA_ISSUER: String = set_mfa_issuer();
 }
 
 // application defaults
 const _HOST: &str = "127.0.0.1";
 const _PORT: u16 = 8080;
+const _REVOCATION_CACHE_TTL: u64 = 30;
+const _DEFAULT_PAGE_SIZE: u64 = 5;
+const _MAX_PAGE_SIZE: u64 = 100;
+const _LOGIN_MAX_FAILURES: u32 = 10;
+const _LOGIN_MAX_FAILURES_PER_IP: u32 = 50;
+const _LOGIN_BACKOFF_BASE: u64 = 1;
+const _LOGIN_LOCKOUT_DURATION: u64 = 900;
+const _MAIL_FROM: &str = "noreply@localhost";
+const _MAIL_OUTBOX_DIR: &str = "outbox";
+const _PASSWORD_RESET_URL: &str = "http://localhost:8080/reset-password";
+const _PASSWORD_RESET_TTL: u64 = 3600;
+const _EMAIL_VERIFICATION_URL: &str = "http://localhost:8080/auth/verify-email";
+const _INVITATION_URL: &str = "http://localhost:8080/accept-invitation";
+const _INVITATION_TTL: u64 = 604800;
+const _MFA_ISSUER: &str = "actix-fullstack";
 
 fn get_env(key: &str) -> Result<String, VarError> {
     dotenv::dotenv().ok();
@@ -36,7 +73,7 @@ fn set_port() -> u16 {
 
 pub fn get_address() -> (String, u16) {
     let host = (*HOST).clone();
-    let port = (*PORT).clone();
+    let port = *PORT;
 
     (host, port)
 }
@@ -64,4 +101,241 @@ fn set_secret() -> String {
 
 pub fn get_secret() -> String {
     (*SECRET).clone()
-}
\ No newline at end of file
+}
+
+fn set_password_params() -> Params {
+    // cost parameters for Argon2id, see `utils::password`. Changing any of these makes
+    // existing hashes get upgraded the next time their owner logs in
+    let m_cost = get_env("ARGON2_MEMORY_COST").ok().and_then(|v| v.parse::<u32>().ok()).unwrap_or(Params::DEFAULT_M_COST);
+    let t_cost = get_env("ARGON2_TIME_COST").ok().and_then(|v| v.parse::<u32>().ok()).unwrap_or(Params::DEFAULT_T_COST);
+    let p_cost = get_env("ARGON2_PARALLELISM").ok().and_then(|v| v.parse::<u32>().ok()).unwrap_or(Params::DEFAULT_P_COST);
+
+    Params::new(m_cost, t_cost, p_cost, None).unwrap_or_else(|err| {
+        log::error!("Invalid Argon2 parameters ({}), falling back to defaults", err);
+        Params::default()
+    })
+}
+
+pub fn get_password_params() -> Params {
+    (*PASSWORD_PARAMS).clone()
+}
+
+fn set_revocation_cache_ttl() -> u64 {
+    // seconds a revocation check is cached in memory, `0` turns the cache off
+    let ttl = get_env("REVOCATION_CACHE_TTL").unwrap_or(_REVOCATION_CACHE_TTL.to_string());
+    ttl.parse::<u64>().unwrap_or(_REVOCATION_CACHE_TTL)
+}
+
+pub fn get_revocation_cache_ttl() -> Duration {
+    Duration::from_secs(*REVOCATION_CACHE_TTL)
+}
+
+fn set_jwt_keys() -> KeySet {
+    // without an asymmetric signing key, tokens are signed with HS512 and `SECRET`
+    let signing_key = match get_env("JWT_SIGNING_KEY") {
+        Ok(path) if !path.is_empty() => path,
+        _ => return KeySet::from_secret(&get_secret()),
+    };
+    let signing_kid = get_env("JWT_SIGNING_KID").unwrap();
+    // `kid=path` pairs separated by commas, e.g. `2026-10=keys/2026-10.pub.pem,2026-04=keys/2026-04.pub.pem`
+    let public_keys = get_env("JWT_PUBLIC_KEYS").unwrap_or_default()
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(kid, path)| (kid.trim().to_string(), path.trim().to_string()))
+        .collect();
+
+    KeySet::from_pem_files(&signing_kid, &signing_key, public_keys).unwrap()
+}
+
+pub fn get_jwt_keys() -> &'static KeySet {
+    &JWT_KEYS
+}
+
+fn set_default_page_size() -> u64 {
+    let page_size = get_env("DEFAULT_PAGE_SIZE").unwrap_or(_DEFAULT_PAGE_SIZE.to_string());
+    page_size.parse::<u64>().unwrap_or(_DEFAULT_PAGE_SIZE)
+}
+
+pub fn get_default_page_size() -> u64 {
+    *DEFAULT_PAGE_SIZE
+}
+
+fn set_max_page_size() -> u64 {
+    let page_size = get_env("MAX_PAGE_SIZE").unwrap_or(_MAX_PAGE_SIZE.to_string());
+    page_size.parse::<u64>().unwrap_or(_MAX_PAGE_SIZE)
+}
+
+pub fn get_max_page_size() -> u64 {
+    *MAX_PAGE_SIZE
+}
+
+fn set_login_max_failures() -> u32 {
+    // failed logins in a row after which an account is locked for `LOGIN_LOCKOUT_DURATION`
+    let failures = get_env("LOGIN_MAX_FAILURES").unwrap_or(_LOGIN_MAX_FAILURES.to_string());
+    failures.parse::<u32>().unwrap_or(_LOGIN_MAX_FAILURES)
+}
+
+pub fn get_login_max_failures() -> u32 {
+    *LOGIN_MAX_FAILURES
+}
+
+fn set_login_max_failures_per_ip() -> u32 {
+    // the same for all logins from one IP address, whichever accounts they try, `0` turns it off
+    let failures = get_env("LOGIN_MAX_FAILURES_PER_IP").unwrap_or(_LOGIN_MAX_FAILURES_PER_IP.to_string());
+    failures.parse::<u32>().unwrap_or(_LOGIN_MAX_FAILURES_PER_IP)
+}
+
+pub fn get_login_max_failures_per_ip() -> u32 {
+    *LOGIN_MAX_FAILURES_PER_IP
+}
+
+fn set_trusted_proxies() -> Vec<IpAddr> {
+    // comma separated addresses of reverse proxies whose `Forwarded` and `X-Forwarded-For`
+    // headers tell the client address; by default there are none and the peer address is used
+    let proxies = get_env("TRUSTED_PROXIES").unwrap_or_default();
+    proxies
+        .split(',')
+        .map(str::trim)
+        .filter(|proxy| !proxy.is_empty())
+        .filter_map(|proxy| match proxy.parse::<IpAddr>() {
+            Ok(address) => Some(address),
+            Err(_) => {
+                log::error!("Ignoring trusted proxy `{}`, it is not an IP address", proxy);
+                None
+            }
+        })
+        .collect()
+}
+
+pub fn get_trusted_proxies() -> &'static [IpAddr] {
+    &TRUSTED_PROXIES
+}
+
+fn set_login_backoff_base() -> u64 {
+    // seconds to wait after the first failure that is slowed down, doubling with every further one
+    let seconds = get_env("LOGIN_BACKOFF_BASE").unwrap_or(_LOGIN_BACKOFF_BASE.to_string());
+    seconds.parse::<u64>().unwrap_or(_LOGIN_BACKOFF_BASE)
+}
+
+pub fn get_login_backoff_base() -> Duration {
+    Duration::from_secs(*LOGIN_BACKOFF_BASE)
+}
+
+fn set_login_lockout_duration() -> u64 {
+    // seconds a lockout lasts, failures older than this are forgotten
+    let seconds = get_env("LOGIN_LOCKOUT_DURATION").unwrap_or(_LOGIN_LOCKOUT_DURATION.to_string());
+    seconds.parse::<u64>().unwrap_or(_LOGIN_LOCKOUT_DURATION)
+}
+
+pub fn get_login_lockout_duration() -> Duration {
+    Duration::from_secs(*LOGIN_LOCKOUT_DURATION)
+}
+
+pub fn get_mailer() -> Box<dyn Mailer> {
+    let from = get_env("MAIL_FROM").ok().filter(|from| !from.is_empty()).unwrap_or(_MAIL_FROM.to_string());
+    let from = from.parse().unwrap();
+
+    // `smtp` needs `SMTP_URL`; `file` writes emails to `MAIL_OUTBOX_DIR`; `memory` keeps them
+    // in the process and is only useful for testing
+    match get_env("MAIL_BACKEND").unwrap_or_default().as_str() {
+        "smtp" => Box::new(SmtpMailer::new(&get_env("SMTP_URL").unwrap(), from).unwrap()),
+        "memory" => Box::new(MemoryMailer::new()),
+        _ => {
+            let outbox = get_env("MAIL_OUTBOX_DIR").ok().filter(|dir| !dir.is_empty()).unwrap_or(_MAIL_OUTBOX_DIR.to_string());
+            Box::new(FileMailer::new(outbox.into(), from).unwrap())
+        }
+    }
+}
+
+fn set_password_reset_url() -> String {
+    // the page that asks for a new password, the token is appended as `?token=`
+    get_env("PASSWORD_RESET_URL").ok().filter(|url| !url.is_empty()).unwrap_or(_PASSWORD_RESET_URL.to_string())
+}
+
+pub fn get_password_reset_url() -> String {
+    (*PASSWORD_RESET_URL).clone()
+}
+
+fn set_password_reset_ttl() -> u64 {
+    // seconds a password reset link stays valid
+    let ttl = get_env("PASSWORD_RESET_TTL").unwrap_or(_PASSWORD_RESET_TTL.to_string());
+    ttl.parse::<u64>().unwrap_or(_PASSWORD_RESET_TTL)
+}
+
+pub fn get_password_reset_ttl() -> Duration {
+    Duration::from_secs(*PASSWORD_RESET_TTL)
+}
+
+fn set_email_verification_url() -> String {
+    // where `GET /auth/verify-email` can be reached from outside, the token is appended as `?token=`
+    get_env("EMAIL_VERIFICATION_URL").ok().filter(|url| !url.is_empty()).unwrap_or(_EMAIL_VERIFICATION_URL.to_string())
+}
+
+pub fn get_email_verification_url() -> String {
+    (*EMAIL_VERIFICATION_URL).clone()
+}
+
+fn set_email_verification_required() -> bool {
+    // whether self-registered accounts start inactive until their email address is verified
+    let required = get_env("EMAIL_VERIFICATION_REQUIRED").unwrap_or_default();
+    required.parse::<bool>().unwrap_or(true)
+}
+
+pub fn get_email_verification_required() -> bool {
+    *EMAIL_VERIFICATION_REQUIRED
+}
+
+/// Who can create an account through `POST /auth/register`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegistrationMode {
+    /// anyone
+    Open,
+    /// nobody, accounts come from invitations of admins
+    InviteOnly,
+    /// nobody, only admins create accounts
+    Closed,
+}
+
+fn set_registration_mode() -> RegistrationMode {
+    match get_env("REGISTRATION_MODE").unwrap_or_default().as_str() {
+        "open" => RegistrationMode::Open,
+        "invite_only" => RegistrationMode::InviteOnly,
+        "closed" | "" => RegistrationMode::Closed,
+        mode => {
+            log::error!("Unknown registration mode `{}`, registration is closed", mode);
+            RegistrationMode::Closed
+        }
+    }
+}
+
+pub fn get_registration_mode() -> RegistrationMode {
+    *REGISTRATION_MODE
+}
+
+fn set_invitation_url() -> String {
+    // the page that asks an invited user for their details, the token is appended as `?token=`
+    get_env("INVITATION_URL").ok().filter(|url| !url.is_empty()).unwrap_or(_INVITATION_URL.to_string())
+}
+
+pub fn get_invitation_url() -> String {
+    (*INVITATION_URL).clone()
+}
+
+fn set_invitation_ttl() -> u64 {
+    // seconds an invitation stays valid, a week by default
+    let ttl = get_env("INVITATION_TTL").unwrap_or(_INVITATION_TTL.to_string());
+    ttl.parse::<u64>().unwrap_or(_INVITATION_TTL)
+}
+
+pub fn get_invitation_ttl() -> Duration {
+    Duration::from_secs(*INVITATION_TTL)
+}
+
+fn set_mfa_issuer() -> String {
+    // the name authenticator apps show next to the account
+    get_env("MFA_ISSUER").ok().filter(|issuer| !issuer.is_empty()).unwrap_or(_MFA_ISSUER.to_string())
+}
+
+pub fn get_mfa_issuer() -> String {
+    (*MFA_ISSUER).clone()
+}
@@ -0,0 +1,358 @@
+use crate::utils::errors::AppError;
+use crate::utils::filter::parse_value;
+use crate::utils::pagination::{validate_page_size, with_query_param, PaginationQuery};
+use crate::utils::sort::{Sort, SortKey};
+use actix_web::http::header::LINK;
+use actix_web::{HttpRequest, HttpResponse};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use sea_orm::{ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter, QuerySelect, Select, Value};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Direction {
+    Next,
+    Prev,
+}
+
+/// What a cursor stands for: the sort key values of the last item seen and the direction to
+/// continue in. Without `after` it points at the first (`next`) or last (`prev`) page.
+#[derive(Serialize, Deserialize)]
+struct Position {
+    dir: Direction,
+    sort: String,
+    after: Option<Vec<JsonValue>>,
+}
+
+impl Position {
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    fn decode(cursor: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+/// Keyset pagination. Unlike `Pagination`, a page is found by seeking past the sort key values
+/// of the last item seen, so pages stay consistent while rows are inserted or deleted and deep
+/// pages cost as much as the first one.
+///
+/// Cursors are opaque base64url strings. An empty `cursor=` starts at the first page.
+pub struct CursorPagination<C> {
+    sort: Sort<C>,
+    page_size: u64,
+    position: Position,
+}
+
+impl<C: ColumnTrait> CursorPagination<C> {
+    /// Returns `None` when the query asks for offset pagination instead.
+    pub fn from_query(query: &PaginationQuery, sort: Sort<C>) -> Result<Option<Self>, AppError> {
+        let Some(cursor) = query.cursor() else {
+            return Ok(None);
+        };
+        let page_size = query.page_size();
+
+        let mut errors = BTreeMap::new();
+        if query.page().is_some() {
+            errors.insert("page".to_string(), vec!["cannot be combined with cursor".to_string()]);
+        }
+        validate_page_size(page_size, &mut errors);
+
+        let position = if cursor.is_empty() {
+            Position { dir: Direction::Next, sort: sort.to_string(), after: None }
+        } else {
+            match Position::decode(cursor) {
+                Some(position) if position.sort != sort.to_string() => {
+                    errors.insert("cursor".to_string(), vec![format!("was issued for sort `{}`", position.sort)]);
+                    position
+                }
+                Some(position) => position,
+                None => return Err(AppError::BadRequest("Invalid cursor".to_string())),
+            }
+        };
+        if !errors.is_empty() {
+            return Err(AppError::Validation(errors));
+        }
+
+        Ok(Some(CursorPagination { sort, page_size, position }))
+    }
+
+    pub async fn fetch<E>(&self, select: Select<E>, db: &DatabaseConnection) -> Result<CursorPage<E::Model>, AppError>
+    where
+        E: EntityTrait<Column = C>,
+        E::Model: Serialize,
+    {
+        let backward = self.position.dir == Direction::Prev;
+        let mut select = self.sort.apply(select, backward);
+        if let Some(after) = &self.position.after {
+            select = select.filter(self.seek(after, backward)?);
+        }
+
+        // one extra row tells us whether there is another page in this direction
+        let mut items = select.limit(self.page_size + 1).all(db).await?;
+        let has_more = items.len() as u64 > self.page_size;
+        items.truncate(self.page_size as usize);
+        if backward {
+            items.reverse();
+        }
+
+        let bounded = self.position.after.is_some();
+        let (has_prev, has_next) = if backward { (has_more, bounded) } else { (bounded, has_more) };
+        let prev_cursor = items.first().filter(|_| has_prev).map(|item| self.cursor(Direction::Prev, item));
+        let next_cursor = items.last().filter(|_| has_next).map(|item| self.cursor(Direction::Next, item));
+
+        Ok(CursorPage {
+            items,
+            page_size: self.page_size,
+            sort: self.sort.to_string(),
+            prev_cursor,
+            next_cursor,
+        })
+    }
+
+    fn cursor<M: Serialize>(&self, dir: Direction, item: &M) -> String {
+        let item = serde_json::to_value(item).unwrap_or_default();
+        let after = self.sort.keys().iter().map(|key| item[key.name].clone()).collect();
+        Position { dir, sort: self.sort.to_string(), after: Some(after) }.encode()
+    }
+
+    /// The condition for rows strictly beyond `after` in the direction of travel, i.e.
+    /// `k1 > v1 OR (k1 = v1 AND (k2 > v2 OR (k2 = v2 AND ...)))` with `NULL`s sorting last.
+    fn seek(&self, after: &[JsonValue], backward: bool) -> Result<Condition, AppError> {
+        let invalid = || AppError::BadRequest("Invalid cursor".to_string());
+        let keys = self.sort.keys();
+        if after.len() != keys.len() {
+            return Err(invalid());
+        }
+
+        let mut condition: Option<Condition> = None;
+        for (key, value) in keys.iter().zip(after).rev() {
+            let value = to_value(key.column, value).ok_or_else(invalid)?;
+            let equal = match &value {
+                Some(value) => key.column.eq(value.clone()),
+                None => key.column.is_null(),
+            };
+            let rest = condition.map(|rest| Condition::all().add(equal).add(rest));
+            condition = match (beyond(key, value, backward), rest) {
+                (Some(beyond), Some(rest)) => Some(Condition::any().add(beyond).add(rest)),
+                (Some(beyond), None) => Some(Condition::any().add(beyond)),
+                (None, rest) => rest,
+            };
+        }
+
+        condition.ok_or_else(invalid)
+    }
+}
+
+/// Rows that sort strictly after `value` on this key alone, or `None` when no row can.
+fn beyond<C: ColumnTrait>(key: &SortKey<C>, value: Option<Value>, backward: bool) -> Option<Condition> {
+    let Some(value) = value else {
+        return backward.then(|| Condition::all().add(key.column.is_not_null()));
+    };
+
+    let compare = if key.descending == backward { key.column.gt(value) } else { key.column.lt(value) };
+    if backward {
+        Some(Condition::all().add(compare))
+    } else {
+        Some(Condition::any().add(compare).add(key.column.is_null()))
+    }
+}
+
+/// Converts a sort key value from a cursor back into a value of the column's type. The outer
+/// `Option` is `None` when the value does not fit the column.
+fn to_value<C: ColumnTrait>(column: C, value: &JsonValue) -> Option<Option<Value>> {
+    let raw = match value {
+        JsonValue::Null => return Some(None),
+        JsonValue::String(string) => string.clone(),
+        JsonValue::Number(_) | JsonValue::Bool(_) => value.to_string(),
+        _ => return None,
+    };
+    parse_value(column, &raw).map(Some)
+}
+
+pub struct CursorPage<T> {
+    pub items: Vec<T>,
+    page_size: u64,
+    sort: String,
+    prev_cursor: Option<String>,
+    next_cursor: Option<String>,
+}
+
+impl<T> CursorPage<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> CursorPage<U> {
+        CursorPage {
+            items: self.items.into_iter().map(f).collect(),
+            page_size: self.page_size,
+            sort: self.sort,
+            prev_cursor: self.prev_cursor,
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+impl<T: Serialize> CursorPage<T> {
+    /// Builds the response for one page, with the items listed under `key`.
+    pub fn response(&self, request: &HttpRequest, key: &str) -> HttpResponse {
+        let prev = self.prev_cursor.as_deref().map(|cursor| with_query_param(request, "cursor", Some(cursor)));
+        let next = self.next_cursor.as_deref().map(|cursor| with_query_param(request, "cursor", Some(cursor)));
+        let last = Position { dir: Direction::Prev, sort: self.sort.clone(), after: None }.encode();
+
+        let mut links = vec![];
+        if let Some(next) = &next {
+            links.push(format!("<{}>; rel=\"next\"", next));
+        }
+        if let Some(prev) = &prev {
+            links.push(format!("<{}>; rel=\"prev\"", prev));
+        }
+        links.push(format!("<{}>; rel=\"first\"", with_query_param(request, "cursor", Some(""))));
+        links.push(format!("<{}>; rel=\"last\"", with_query_param(request, "cursor", Some(&last))));
+
+        let mut body = json!({
+            "page_size": self.page_size,
+            "sort": self.sort,
+            "prev_cursor": self.prev_cursor,
+            "next_cursor": self.next_cursor,
+            "prev": prev,
+            "next": next,
+        });
+        body[key] = json!(self.items);
+
+        HttpResponse::Ok()
+            .insert_header((LINK, links.join(", ")))
+            .json(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entity::login_event::{Column, Entity as LoginEvent, Model};
+    use sea_orm::{DatabaseBackend, MockDatabase, QueryTrait};
+
+    const FIELDS: &[(&str, Column)] = &[("ip", Column::Ip)];
+
+    fn pagination(sort: &str, position: Position) -> CursorPagination<Column> {
+        let sort = Sort::parse(Some(sort), FIELDS, ("id", Column::Id)).unwrap();
+        CursorPagination { sort, page_size: 2, position }
+    }
+
+    fn start(dir: Direction) -> Position {
+        Position { dir, sort: String::new(), after: None }
+    }
+
+    // the `WHERE` clause of a query for the rows beyond `after`
+    fn seek(sort: &str, after: JsonValue, backward: bool) -> String {
+        let condition = pagination(sort, start(Direction::Next)).seek(after.as_array().unwrap(), backward).unwrap();
+        let sql = LoginEvent::find().filter(condition).build(DatabaseBackend::Postgres).to_string();
+        sql.split_once(" WHERE ").unwrap().1.replace("\"login_event\".", "")
+    }
+
+    fn event(id: i64) -> Model {
+        Model {
+            id,
+            user_id: None,
+            username: "root".to_string(),
+            outcome: "success".to_string(),
+            ip: None,
+            user_agent: None,
+            created_at: chrono::DateTime::parse_from_rfc3339("2026-10-18T00:00:00Z").unwrap(),
+        }
+    }
+
+    #[test]
+    fn seeks_forward_on_ascending_keys() {
+        assert_eq!(
+            seek("ip", json!(["10.0.0.1", 5]), false),
+            r#""ip" > '10.0.0.1' OR "ip" IS NULL OR ("ip" = '10.0.0.1' AND ("id" > 5 OR "id" IS NULL))"#,
+        );
+    }
+
+    #[test]
+    fn seeks_backward_on_ascending_keys() {
+        assert_eq!(
+            seek("ip", json!(["10.0.0.1", 5]), true),
+            r#""ip" < '10.0.0.1' OR ("ip" = '10.0.0.1' AND "id" < 5)"#,
+        );
+    }
+
+    #[test]
+    fn seeks_forward_on_descending_keys() {
+        assert_eq!(
+            seek("-ip", json!(["10.0.0.1", 5]), false),
+            r#""ip" < '10.0.0.1' OR "ip" IS NULL OR ("ip" = '10.0.0.1' AND ("id" < 5 OR "id" IS NULL))"#,
+        );
+    }
+
+    #[test]
+    fn seeks_backward_on_descending_keys() {
+        assert_eq!(
+            seek("-ip", json!(["10.0.0.1", 5]), true),
+            r#""ip" > '10.0.0.1' OR ("ip" = '10.0.0.1' AND "id" > 5)"#,
+        );
+    }
+
+    #[test]
+    fn seeks_forward_past_nulls() {
+        // `NULL`s sort last, so only other `NULL`s can follow one
+        assert_eq!(seek("ip", json!([null, 5]), false), r#""ip" IS NULL AND ("id" > 5 OR "id" IS NULL)"#);
+        assert_eq!(seek("-ip", json!([null, 5]), false), r#""ip" IS NULL AND ("id" < 5 OR "id" IS NULL)"#);
+    }
+
+    #[test]
+    fn seeks_backward_past_nulls() {
+        assert_eq!(seek("ip", json!([null, 5]), true), r#""ip" IS NOT NULL OR ("ip" IS NULL AND "id" < 5)"#);
+        assert_eq!(seek("-ip", json!([null, 5]), true), r#""ip" IS NOT NULL OR ("ip" IS NULL AND "id" > 5)"#);
+    }
+
+    #[test]
+    fn rejects_cursors_that_do_not_fit_the_sort() {
+        let pagination = pagination("ip", start(Direction::Next));
+        assert!(pagination.seek(&[json!(5)], false).is_err());
+        assert!(pagination.seek(&[json!("10.0.0.1"), json!("five")], false).is_err());
+        assert!(pagination.seek(&[json!("10.0.0.1"), json!([5])], false).is_err());
+    }
+
+    #[actix_web::test]
+    async fn jumps_to_the_last_page() {
+        // the last page is read backwards, so the database returns it newest first
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![event(9), event(8), event(7)]])
+            .into_connection();
+
+        let page = pagination("id", start(Direction::Prev)).fetch(LoginEvent::find(), &db).await.unwrap();
+        let ids: Vec<i64> = page.items.iter().map(|item| item.id).collect();
+        assert_eq!(ids, vec![8, 9]);
+        assert!(page.next_cursor.is_none());
+
+        let prev = Position::decode(page.prev_cursor.as_deref().unwrap()).unwrap();
+        assert!(prev.dir == Direction::Prev);
+        assert_eq!(prev.sort, "id");
+        assert_eq!(prev.after, Some(vec![json!(8)]));
+
+        let log = format!("{:?}", db.into_transaction_log());
+        assert!(log.contains(r#"ORDER BY \"login_event\".\"id\" DESC NULLS FIRST"#), "{}", log);
+    }
+
+    #[actix_web::test]
+    async fn goes_back_from_the_last_page() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![event(7), event(6), event(5)]])
+            .into_connection();
+
+        let position = Position { dir: Direction::Prev, sort: "id".to_string(), after: Some(vec![json!(8)]) };
+        let page = pagination("id", position).fetch(LoginEvent::find(), &db).await.unwrap();
+        let ids: Vec<i64> = page.items.iter().map(|item| item.id).collect();
+        assert_eq!(ids, vec![6, 7]);
+
+        let prev = Position::decode(page.prev_cursor.as_deref().unwrap()).unwrap();
+        let next = Position::decode(page.next_cursor.as_deref().unwrap()).unwrap();
+        assert_eq!(prev.after, Some(vec![json!(6)]));
+        assert!(next.dir == Direction::Next);
+        assert_eq!(next.after, Some(vec![json!(7)]));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::utils::response::ProblemDetails;
+use actix_web::http::header::{ContentType, RETRY_AFTER};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use sea_orm::sqlx;
+use sea_orm::{DbErr, RuntimeErr, SqlErr};
+use std::collections::BTreeMap;
+use std::fmt;
+use uuid::Uuid;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Every error a handler, extractor or middleware can answer with. Responses are
+/// `application/problem+json` bodies as described in RFC 7807, which lets handlers use `?`
+/// instead of building error responses by hand.
+#[derive(Debug)]
+pub enum AppError {
+    // authentication
+    MissingToken,
+    InvalidTokenFormat,
+    InvalidToken(jsonwebtoken::errors::Error),
+    RevokedToken,
+    UnknownUser,
+    InactiveUser,
+    Unauthorized(String),
+    /// seconds until the client may try again, sent as `Retry-After`
+    TooManyRequests(u64),
+    // authorization
+    Forbidden(String),
+    // client errors
+    BadRequest(String),
+    NotFound(String),
+    Conflict(String),
+    PreconditionFailed(String),
+    UnsupportedMediaType,
+    Validation(BTreeMap<String, Vec<String>>),
+    // server errors
+    Database(DbErr),
+    Internal(String),
+}
+
+impl AppError {
+    /// The last part of the problem `type` URI, e.g. `/problems/not-found`.
+    fn problem_type(&self) -> &'static str {
+        match self {
+            AppError::MissingToken => "missing-token",
+            AppError::InvalidTokenFormat | AppError::InvalidToken(_) => "invalid-token",
+            AppError::RevokedToken => "revoked-token",
+            AppError::UnknownUser | AppError::Unauthorized(_) => "unauthorized",
+            AppError::InactiveUser => "inactive-user",
+            AppError::TooManyRequests(_) => "too-many-requests",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::BadRequest(_) => "bad-request",
+            AppError::NotFound(_) => "not-found",
+            AppError::Conflict(_) => "conflict",
+            AppError::PreconditionFailed(_) => "precondition-failed",
+            AppError::UnsupportedMediaType => "unsupported-media-type",
+            AppError::Validation(_) => "validation-error",
+            AppError::Database(err) => match classify(err) {
+                DatabaseFault::Conflict => "conflict",
+                DatabaseFault::NotFound => "not-found",
+                DatabaseFault::Unavailable => "service-unavailable",
+                DatabaseFault::Other => "internal-error",
+            },
+            AppError::Internal(_) => "internal-error",
+        }
+    }
+}
+
+enum DatabaseFault {
+    Conflict,
+    NotFound,
+    Unavailable,
+    Other,
+}
+
+fn classify(err: &DbErr) -> DatabaseFault {
+    if let Some(SqlErr::UniqueConstraintViolation(_)) = err.sql_err() {
+        return DatabaseFault::Conflict;
+    }
+
+    match err {
+        DbErr::RecordNotFound(_) => DatabaseFault::NotFound,
+        DbErr::ConnectionAcquire(_) | DbErr::Conn(_) => DatabaseFault::Unavailable,
+        DbErr::Exec(RuntimeErr::SqlxError(sqlx_err)) | DbErr::Query(RuntimeErr::SqlxError(sqlx_err)) => match sqlx_err {
+            sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed | sqlx::Error::WorkerCrashed => DatabaseFault::Unavailable,
+            _ => DatabaseFault::Other,
+        },
+        _ => DatabaseFault::Other,
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::MissingToken => write!(f, "Missing authentication token"),
+            AppError::InvalidTokenFormat => write!(f, "Invalid token format"),
+            AppError::InvalidToken(err) => write!(f, "Invalid token: {}", err),
+            AppError::RevokedToken => write!(f, "Token has been revoked"),
+            AppError::UnknownUser => write!(f, "Token belongs to a user that no longer exists"),
+            AppError::InactiveUser => write!(f, "This account is not active, verify its email address or ask an admin to activate it"),
+            AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::BadRequest(message)
+            | AppError::NotFound(message)
+            | AppError::Conflict(message)
+            | AppError::PreconditionFailed(message) => write!(f, "{}", message),
+            AppError::TooManyRequests(_) => write!(f, "Too many attempts, try again later"),
+            AppError::UnsupportedMediaType => write!(f, "Expected a JSON body"),
+            AppError::Validation(_) => write!(f, "Validation failed"),
+            // never show raw database errors to clients, they are logged instead
+            AppError::Database(err) => match classify(err) {
+                DatabaseFault::Conflict => write!(f, "A record with the same unique value already exists"),
+                DatabaseFault::NotFound => write!(f, "The requested record does not exist"),
+                DatabaseFault::Unavailable => write!(f, "The database is currently unavailable"),
+                DatabaseFault::Other => write!(f, "An unexpected error occurred"),
+            },
+            AppError::Internal(_) => write!(f, "An unexpected error occurred"),
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::MissingToken
+            | AppError::InvalidToken(_)
+            | AppError::RevokedToken
+            | AppError::UnknownUser
+            | AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::InvalidTokenFormat | AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Forbidden(_) | AppError::InactiveUser => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            AppError::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Database(err) => match classify(err) {
+                DatabaseFault::Conflict => StatusCode::CONFLICT,
+                DatabaseFault::NotFound => StatusCode::NOT_FOUND,
+                DatabaseFault::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+                DatabaseFault::Other => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        // identifies this occurrence, so a client report can be matched with the logs
+        let instance = format!("urn:uuid:{}", Uuid::new_v4());
+
+        match self {
+            AppError::Database(err) => log::error!("{}: database error: {}", instance, err),
+            AppError::Internal(err) => log::error!("{}: internal error: {}", instance, err),
+            _ => log::debug!("{}: {}", instance, self),
+        }
+
+        let problem = ProblemDetails {
+            problem_type: format!("/problems/{}", self.problem_type()),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail: self.to_string(),
+            instance,
+            errors: match self {
+                AppError::Validation(errors) => Some(errors.clone()),
+                _ => None,
+            },
+        };
+
+        let mut response = HttpResponse::build(status);
+        response.insert_header(ContentType(PROBLEM_JSON.parse().unwrap()));
+        if let AppError::TooManyRequests(seconds) = self {
+            response.insert_header((RETRY_AFTER, seconds.to_string()));
+        }
+        response.body(serde_json::to_string(&problem).unwrap())
+    }
+}
+
+impl From<DbErr> for AppError {
+    fn from(err: DbErr) -> Self {
+        AppError::Database(err)
+    }
+}
+
+impl From<argon2::password_hash::Error> for AppError {
+    fn from(err: argon2::password_hash::Error) -> Self {
+        AppError::Internal(format!("password hashing failed: {}", err))
+    }
+}
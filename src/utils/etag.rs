@@ -0,0 +1,52 @@
+use crate::utils::errors::AppError;
+use actix_web::http::header::{EntityTag, Header, IfMatch, IfNoneMatch, IF_MATCH, IF_NONE_MATCH};
+use actix_web::HttpRequest;
+
+/// The entity tag of a resource at the given version. Tags are strong, so they can be sent
+/// back in `If-Match`.
+pub fn etag(version: i32) -> EntityTag {
+    EntityTag::new_strong(version.to_string())
+}
+
+/// Fails with `412 Precondition Failed` when the request has an `If-Match` header that doesn't
+/// match `current`. Requests without the header always pass.
+pub fn check_if_match(request: &HttpRequest, current: &EntityTag) -> Result<(), AppError> {
+    if !request.headers().contains_key(IF_MATCH) {
+        return Ok(());
+    }
+
+    let matches = match IfMatch::parse(request) {
+        Ok(IfMatch::Any) => true,
+        Ok(IfMatch::Items(tags)) => tags.iter().any(|tag| tag.strong_eq(current)),
+        Err(_) => return Err(AppError::BadRequest("Invalid If-Match header".to_string())),
+    };
+    if !matches {
+        return Err(AppError::PreconditionFailed("The resource has been modified since it was last read".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Whether a `GET` can be answered with `304 Not Modified` because the client's copy, named by
+/// `If-None-Match`, is still `current`.
+pub fn is_not_modified(request: &HttpRequest, current: &EntityTag) -> bool {
+    if !request.headers().contains_key(IF_NONE_MATCH) {
+        return false;
+    }
+
+    match IfNoneMatch::parse(request) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(current)),
+        Err(_) => false,
+    }
+}
+
+/// The error for a conditional write that lost a race with another write after its checks
+/// passed: a failed precondition if the client sent one, a conflict otherwise.
+pub fn write_conflict(request: &HttpRequest) -> AppError {
+    if request.headers().contains_key(IF_MATCH) {
+        AppError::PreconditionFailed("The resource has been modified since it was last read".to_string())
+    } else {
+        AppError::Conflict("The resource was modified by another request, please retry".to_string())
+    }
+}
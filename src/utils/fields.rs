@@ -0,0 +1,94 @@
+use crate::utils::errors::AppError;
+use sea_orm::sea_query::Expr;
+use sea_orm::{ColumnTrait, EntityTrait, QuerySelect, Select};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+
+#[derive(Deserialize, Clone)]
+pub struct FieldsQuery {
+    pub fields: Option<String>,
+    pub include: Option<String>,
+}
+
+/// A sparse fieldset like `fields=id,username`. Only the listed columns are read from the
+/// database and only the listed fields are returned. Every field has to be in the allowlist of
+/// `(name, column)` pairs the caller passes in.
+pub struct Fields<C> {
+    selected: Vec<(&'static str, C)>,
+}
+
+impl<C: ColumnTrait> Fields<C> {
+    /// Returns `None` when all fields were asked for.
+    pub fn parse(raw: Option<&str>, allowed: &[(&'static str, C)]) -> Result<Option<Self>, AppError> {
+        let Some(raw) = raw.map(str::trim).filter(|raw| !raw.is_empty()) else {
+            return Ok(None);
+        };
+
+        let mut selected: Vec<(&'static str, C)> = vec![];
+        let mut errors = vec![];
+        for name in raw.split(',').map(str::trim) {
+            match allowed.iter().find(|(allowed_name, _)| *allowed_name == name) {
+                Some(field) if !selected.iter().any(|(selected_name, _)| selected_name == &field.0) => selected.push(*field),
+                Some(_) => {}
+                None => errors.push(format!("`{}` is not an available field", name)),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(AppError::Validation(BTreeMap::from([("fields".to_string(), errors)])));
+        }
+
+        Ok(Some(Fields { selected }))
+    }
+
+    /// Reads only the selected columns, plus the `required` ones the query itself needs, like
+    /// the primary key or sort keys. Every other column is read as `NULL`, so rows still load
+    /// into the entity's model as long as its other fields are optional.
+    pub fn apply<E: EntityTrait<Column = C>>(&self, select: Select<E>, required: &[C]) -> Select<E> {
+        let is_read = |column: &C| {
+            required.iter().chain(self.selected.iter().map(|(_, column)| column)).any(|read| read.as_str() == column.as_str())
+        };
+
+        E::Column::iter().fold(select.select_only(), |select, column| {
+            if is_read(&column) {
+                select.column(column)
+            } else {
+                select.column_as(Expr::cust("NULL"), column.as_str())
+            }
+        })
+    }
+
+    /// Serializes `item` with only the selected fields.
+    pub fn project<T: Serialize>(&self, item: &T) -> JsonValue {
+        match serde_json::to_value(item) {
+            Ok(JsonValue::Object(mut object)) => {
+                object.retain(|key, _| self.selected.iter().any(|(name, _)| name == key));
+                JsonValue::Object(object)
+            }
+            Ok(value) => value,
+            Err(_) => JsonValue::Null,
+        }
+    }
+}
+
+/// Checks an `include=` list of related resources against the ones that can be embedded.
+pub fn parse_include(raw: Option<&str>, allowed: &[&'static str]) -> Result<Vec<&'static str>, AppError> {
+    let Some(raw) = raw.map(str::trim).filter(|raw| !raw.is_empty()) else {
+        return Ok(vec![]);
+    };
+
+    let mut included: Vec<&'static str> = vec![];
+    let mut errors = vec![];
+    for name in raw.split(',').map(str::trim) {
+        match allowed.iter().find(|allowed_name| **allowed_name == name) {
+            Some(name) if !included.contains(name) => included.push(name),
+            Some(_) => {}
+            None => errors.push(format!("`{}` cannot be included", name)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(AppError::Validation(BTreeMap::from([("include".to_string(), errors)])));
+    }
+
+    Ok(included)
+}
@@ -0,0 +1,146 @@
+use crate::utils::errors::AppError;
+use actix_web::HttpRequest;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
+use sea_orm::sea_query::{Expr, Func, LikeExpr};
+use sea_orm::{ColumnTrait, ColumnType, Condition, EntityTrait, QueryFilter, Select, Value};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Query parameters that belong to listing itself and are never read as field filters.
+pub const RESERVED_PARAMS: &[&str] = &["page", "page_size", "cursor", "sort", "q", "fields", "include"];
+
+#[derive(Deserialize, Clone)]
+pub struct SearchQuery {
+    pub q: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Operator {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    In,
+    IsNull,
+}
+
+impl Operator {
+    fn from_suffix(suffix: &str) -> Option<Self> {
+        match suffix {
+            "eq" => Some(Operator::Eq),
+            "ne" => Some(Operator::Ne),
+            "gt" => Some(Operator::Gt),
+            "gte" => Some(Operator::Gte),
+            "lt" => Some(Operator::Lt),
+            "lte" => Some(Operator::Lte),
+            "in" => Some(Operator::In),
+            "isnull" => Some(Operator::IsNull),
+            _ => None,
+        }
+    }
+}
+
+/// Field filters read from the query string, like `is_active=true` or
+/// `date_joined__gte=2024-01-01`. The field must be in the allowlist of `(name, column)` pairs
+/// and the value must parse as the column's type. Supported operators are `eq` (the default),
+/// `ne`, `gt`, `gte`, `lt`, `lte`, `in` (comma separated) and `isnull`.
+pub struct Filters {
+    condition: Condition,
+}
+
+impl Filters {
+    pub fn from_request<C: ColumnTrait>(request: &HttpRequest, allowed: &[(&'static str, C)]) -> Result<Self, AppError> {
+        let mut condition = Condition::all();
+        let mut errors: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+        for (param, raw) in url::form_urlencoded::parse(request.query_string().as_bytes()) {
+            if RESERVED_PARAMS.contains(&param.as_ref()) {
+                continue;
+            }
+
+            let (name, operator) = match param.split_once("__") {
+                Some((name, suffix)) => (name, Operator::from_suffix(suffix)),
+                None => (param.as_ref(), Some(Operator::Eq)),
+            };
+            let Some(operator) = operator else {
+                errors.entry(param.to_string()).or_default().push("unknown operator".to_string());
+                continue;
+            };
+            let Some(&(_, column)) = allowed.iter().find(|(allowed_name, _)| *allowed_name == name) else {
+                errors.entry(param.to_string()).or_default().push(format!("cannot filter by `{}`", name));
+                continue;
+            };
+
+            match expression(column, operator, &raw) {
+                Some(expression) => condition = condition.add(expression),
+                None => errors.entry(param.to_string()).or_default().push(format!("`{}` is not a valid value", raw)),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(AppError::Validation(errors));
+        }
+        Ok(Filters { condition })
+    }
+
+    pub fn apply<E: EntityTrait>(&self, select: Select<E>) -> Select<E> {
+        select.filter(self.condition.clone())
+    }
+}
+
+fn expression<C: ColumnTrait>(column: C, operator: Operator, raw: &str) -> Option<Condition> {
+    let expression = match operator {
+        Operator::Eq => column.eq(parse_value(column, raw)?),
+        Operator::Ne => column.ne(parse_value(column, raw)?),
+        Operator::Gt => column.gt(parse_value(column, raw)?),
+        Operator::Gte => column.gte(parse_value(column, raw)?),
+        Operator::Lt => column.lt(parse_value(column, raw)?),
+        Operator::Lte => column.lte(parse_value(column, raw)?),
+        Operator::In => {
+            let values = raw.split(',').map(|raw| parse_value(column, raw.trim())).collect::<Option<Vec<_>>>()?;
+            column.is_in(values)
+        }
+        Operator::IsNull => match raw.parse::<bool>().ok()? {
+            true => column.is_null(),
+            false => column.is_not_null(),
+        },
+    };
+    Some(Condition::all().add(expression))
+}
+
+/// A case-insensitive substring match of `q` against any of `columns`. `%` and `_` in `q` match
+/// themselves.
+pub fn search<C: ColumnTrait>(q: &str, columns: &[C]) -> Condition {
+    let escaped = q.to_lowercase().replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    let pattern = format!("%{}%", escaped);
+
+    columns.iter().fold(Condition::any(), |condition, column| {
+        let lowered = Expr::expr(Func::lower(Expr::col(column.as_column_ref())));
+        condition.add(lowered.like(LikeExpr::new(pattern.clone()).escape('\\')))
+    })
+}
+
+/// Parses a query string or cursor value as a value of the column's type.
+pub fn parse_value<C: ColumnTrait>(column: C, raw: &str) -> Option<Value> {
+    let value = match column.def().get_column_type() {
+        ColumnType::Integer => Value::from(raw.parse::<i32>().ok()?),
+        ColumnType::BigInteger => Value::from(raw.parse::<i64>().ok()?),
+        ColumnType::Boolean => Value::from(raw.parse::<bool>().ok()?),
+        ColumnType::String(_) | ColumnType::Text => Value::from(raw.to_string()),
+        ColumnType::DateTime | ColumnType::Timestamp => Value::from(parse_datetime(raw)?),
+        ColumnType::TimestampWithTimeZone => Value::from(parse_datetime(raw)?.and_utc().fixed_offset()),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Accepts `2024-01-01`, `2024-01-01T10:00:00` and RFC 3339 timestamps with an offset. Times
+/// without an offset are taken as UTC.
+fn parse_datetime(raw: &str) -> Option<NaiveDateTime> {
+    raw.parse::<NaiveDateTime>()
+        .ok()
+        .or_else(|| DateTime::parse_from_rfc3339(raw).ok().map(|datetime| datetime.naive_utc()))
+        .or_else(|| raw.parse::<NaiveDate>().ok().map(|date| date.and_time(NaiveTime::MIN)))
+}
@@ -3,3 +3,16 @@ pub mod config;
 pub mod log;
 pub mod auth;
 pub mod response;
+pub mod password;
+pub mod keys;
+pub mod validation;
+pub mod errors;
+pub mod pagination;
+pub mod sort;
+pub mod cursor;
+pub mod filter;
+pub mod fields;
+pub mod etag;
+pub mod patch;
+pub mod mail;
+pub mod secret;
@@ -0,0 +1,141 @@
+use crate::utils::config::{get_default_page_size, get_max_page_size};
+use crate::utils::errors::AppError;
+use actix_web::http::header::LINK;
+use actix_web::{HttpRequest, HttpResponse};
+use sea_orm::{EntityTrait, QuerySelect, Select};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use url::Url;
+
+pub const X_TOTAL_COUNT: &str = "X-Total-Count";
+
+#[derive(Deserialize, Clone)]
+pub struct PaginationQuery {
+    page: Option<u64>,
+    page_size: Option<u64>,
+    cursor: Option<String>,
+    sort: Option<String>,
+}
+
+impl PaginationQuery {
+    pub fn page(&self) -> Option<u64> {
+        self.page
+    }
+
+    pub fn page_size(&self) -> u64 {
+        self.page_size.unwrap_or(get_default_page_size())
+    }
+
+    pub fn cursor(&self) -> Option<&str> {
+        self.cursor.as_deref()
+    }
+
+    pub fn sort(&self) -> Option<&str> {
+        self.sort.as_deref()
+    }
+}
+
+/// Offset pagination with 1-based pages. Responses carry absolute `prev`/`next` URLs, an
+/// RFC 8288 `Link` header and the total number of items in `X-Total-Count`.
+pub struct Pagination {
+    pub page: u64,
+    pub page_size: u64,
+}
+
+impl Pagination {
+    pub fn from_query(query: &PaginationQuery) -> Result<Self, AppError> {
+        let page = query.page.unwrap_or(1);
+        let page_size = query.page_size();
+
+        let mut errors = BTreeMap::new();
+        if page < 1 {
+            errors.insert("page".to_string(), vec!["must be at least 1".to_string()]);
+        }
+        validate_page_size(page_size, &mut errors);
+        if !errors.is_empty() {
+            return Err(AppError::Validation(errors));
+        }
+
+        Ok(Pagination { page, page_size })
+    }
+
+    pub fn paginate<E: EntityTrait>(&self, select: Select<E>) -> Select<E> {
+        select
+            .offset((self.page - 1) * self.page_size)
+            .limit(self.page_size)
+    }
+
+    pub fn total_pages(&self, total: u64) -> u64 {
+        total.div_ceil(self.page_size)
+    }
+
+    /// Builds the response for one page, with the items listed under `key`.
+    pub fn response<T: Serialize>(&self, request: &HttpRequest, key: &str, items: Vec<T>, total: u64) -> HttpResponse {
+        let total_pages = self.total_pages(total);
+        let prev = (self.page > 1).then(|| page_url(request, (self.page - 1).min(total_pages.max(1))));
+        let next = (self.page < total_pages).then(|| page_url(request, self.page + 1));
+
+        let mut links = vec![];
+        if let Some(next) = &next {
+            links.push(format!("<{}>; rel=\"next\"", next));
+        }
+        if let Some(prev) = &prev {
+            links.push(format!("<{}>; rel=\"prev\"", prev));
+        }
+        links.push(format!("<{}>; rel=\"first\"", page_url(request, 1)));
+        links.push(format!("<{}>; rel=\"last\"", page_url(request, total_pages.max(1))));
+
+        let mut body = json!({
+            "page": self.page,
+            "page_size": self.page_size,
+            "total": total,
+            "total_pages": total_pages,
+            "prev": prev,
+            "next": next,
+        });
+        body[key] = json!(items);
+
+        HttpResponse::Ok()
+            .insert_header((LINK, links.join(", ")))
+            .insert_header((X_TOTAL_COUNT, total.to_string()))
+            .json(body)
+    }
+}
+
+pub fn validate_page_size(page_size: u64, errors: &mut BTreeMap<String, Vec<String>>) {
+    let max_page_size = get_max_page_size();
+    if page_size < 1 || page_size > max_page_size {
+        errors.insert("page_size".to_string(), vec![format!("must be between 1 and {}", max_page_size)]);
+    }
+}
+
+/// The absolute URL of the current request, with `page` swapped out. Other query parameters,
+/// like filters, are kept as they are.
+pub fn page_url(request: &HttpRequest, page: u64) -> String {
+    let page = page.to_string();
+    with_query_param(request, "page", Some(&page))
+}
+
+pub fn with_query_param(request: &HttpRequest, name: &str, value: Option<&str>) -> String {
+    let connection_info = request.connection_info();
+    let base = format!("{}://{}{}", connection_info.scheme(), connection_info.host(), request.path());
+    let mut url = Url::parse(&base).unwrap_or_else(|_| Url::parse("http://localhost/").unwrap());
+
+    let pairs: Vec<(String, String)> = url::form_urlencoded::parse(request.query_string().as_bytes())
+        .filter(|(key, _)| key != name)
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    {
+        let mut query = url.query_pairs_mut();
+        query.extend_pairs(pairs);
+        if let Some(value) = value {
+            query.append_pair(name, value);
+        }
+    }
+    if url.query() == Some("") {
+        url.set_query(None);
+    }
+
+    url.to_string()
+}
@@ -0,0 +1,152 @@
+use crate::utils::config::get_password_params;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{Error, PasswordHash, PasswordHasher as _, PasswordVerifier as _, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use subtle::ConstantTimeEq;
+
+/// Hashes and verifies user passwords with Argon2id. Hashes are stored as PHC strings
+/// (`$argon2id$v=19$m=...,t=...,p=...$salt$hash`) so the parameters travel with every hash.
+pub struct PasswordHasher {
+    pub(crate) params: Params,
+}
+
+impl PasswordHasher {
+    pub fn new() -> Self {
+        PasswordHasher { params: get_password_params() }
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, Error> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = self.argon2().hash_password(password.as_bytes(), &salt)?;
+        Ok(hash.to_string())
+    }
+
+    /// Checks `password` against a stored value in constant time. Values that don't start
+    /// with `$` are passwords saved before hashing was introduced; they are compared as-is
+    /// so that the account can be upgraded by [`PasswordHasher::needs_rehash`] on login. A
+    /// PHC string that can't be parsed is an error, it is never compared as plaintext.
+    pub fn verify(&self, password: &str, stored: &str) -> Result<bool, Error> {
+        if !stored.starts_with('$') {
+            return Ok(password.as_bytes().ct_eq(stored.as_bytes()).into());
+        }
+
+        let hash = PasswordHash::new(stored)?;
+        if hash.hash.is_none() {
+            return Err(Error::PhcStringField);
+        }
+        match self.argon2().verify_password(password.as_bytes(), &hash) {
+            Ok(()) => Ok(true),
+            Err(Error::Password) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Burns roughly the same time as a real verification, so that a login for an unknown
+    /// user cannot be told apart from a wrong password by timing.
+    pub fn dummy_verify(&self, password: &str) {
+        let salt = SaltString::generate(&mut OsRng);
+        let _ = self.argon2().hash_password(password.as_bytes(), &salt);
+    }
+
+    /// Whether a stored value should be replaced: it is plaintext or malformed, not Argon2id,
+    /// or was hashed with parameters other than the configured ones.
+    pub fn needs_rehash(&self, stored: &str) -> bool {
+        let hash = match PasswordHash::new(stored) {
+            Ok(hash) => hash,
+            Err(_) => return true,
+        };
+        if hash.hash.is_none() || hash.algorithm != Algorithm::Argon2id.ident() || hash.version != Some(Version::V0x13.into()) {
+            return true;
+        }
+
+        match Params::try_from(&hash) {
+            Ok(params) => {
+                params.m_cost() != self.params.m_cost()
+                    || params.t_cost() != self.params.t_cost()
+                    || params.p_cost() != self.params.p_cost()
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // cheap parameters, the tests are about the format and not the cost
+    fn hasher(t_cost: u32) -> PasswordHasher {
+        PasswordHasher { params: Params::new(1024, t_cost, 1, None).unwrap() }
+    }
+
+    #[test]
+    fn verifies_its_own_hashes() {
+        let hasher = hasher(1);
+        let hash = hasher.hash("correct horse 1").unwrap();
+
+        assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+        assert!(hasher.verify("correct horse 1", &hash).unwrap());
+    }
+
+    #[test]
+    fn rejects_wrong_passwords() {
+        let hasher = hasher(1);
+        let hash = hasher.hash("correct horse 1").unwrap();
+
+        assert!(!hasher.verify("correct horse 2", &hash).unwrap());
+        assert!(!hasher.verify("", &hash).unwrap());
+    }
+
+    #[test]
+    fn salts_every_hash() {
+        let hasher = hasher(1);
+        assert_ne!(hasher.hash("correct horse 1").unwrap(), hasher.hash("correct horse 1").unwrap());
+    }
+
+    #[test]
+    fn verifies_legacy_plaintext_passwords() {
+        let hasher = hasher(1);
+
+        assert!(hasher.verify("rootpass1", "rootpass1").unwrap());
+        assert!(!hasher.verify("rootpass2", "rootpass1").unwrap());
+        assert!(hasher.needs_rehash("rootpass1"));
+    }
+
+    #[test]
+    fn rehashes_when_the_parameters_change() {
+        let hash = hasher(1).hash("correct horse 1").unwrap();
+
+        assert!(!hasher(1).needs_rehash(&hash));
+        assert!(hasher(2).needs_rehash(&hash));
+        // hashes with other parameters still verify until they are replaced
+        assert!(hasher(2).verify("correct horse 1", &hash).unwrap());
+    }
+
+    #[test]
+    fn rehashes_other_algorithms() {
+        let hasher = hasher(1);
+        let argon2i = Argon2::new(Algorithm::Argon2i, Version::V0x13, hasher.params.clone())
+            .hash_password(b"correct horse 1", &SaltString::generate(&mut OsRng))
+            .unwrap()
+            .to_string();
+
+        assert!(hasher.needs_rehash(&argon2i));
+    }
+
+    #[test]
+    fn fails_on_malformed_hashes() {
+        let hasher = hasher(1);
+        let hash = hasher.hash("correct horse 1").unwrap();
+        let truncated = &hash[..hash.rfind('$').unwrap()];
+
+        for stored in ["$argon2id$v=19$m=1024,t=1,p=1$", "$argon2id$garbage", "$", truncated] {
+            // never compared as plaintext, even when it is typed in literally
+            assert!(hasher.verify(stored, stored).is_err(), "{}", stored);
+            assert!(hasher.needs_rehash(stored), "{}", stored);
+        }
+    }
+}
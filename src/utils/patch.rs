@@ -0,0 +1,91 @@
+use crate::utils::errors::AppError;
+use crate::utils::validation::{parse, ValidatedJson};
+use actix_web::dev::Payload;
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::mime;
+use actix_web::web::Bytes;
+use actix_web::{FromRequest, HttpRequest};
+use json_patch::{Patch, PatchErrorKind, PatchOperation};
+use serde::de::DeserializeOwned;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use validator::Validate;
+
+pub const MERGE_PATCH_JSON: &str = "application/merge-patch+json";
+pub const JSON_PATCH_JSON: &str = "application/json-patch+json";
+
+/// The body of a `PATCH` request. The format is picked by `Content-Type`.
+pub enum PatchBody<T> {
+    /// `application/json`: the fields to change, where absent and `null` both leave a field as
+    /// it is.
+    Partial(T),
+    /// A patch that is applied to the JSON document of the resource.
+    Document(DocumentPatch),
+}
+
+pub enum DocumentPatch {
+    /// RFC 7396 JSON Merge Patch, where `null` removes a field.
+    Merge(JsonValue),
+    /// RFC 6902 JSON Patch, limited to `add`, `remove`, `replace` and `test`.
+    Operations(Patch),
+}
+
+impl DocumentPatch {
+    /// Applies the patch to `document`. Nothing is applied when any operation fails, and a
+    /// failed `test` is answered with `409 Conflict`.
+    pub fn apply(&self, mut document: JsonValue) -> Result<JsonValue, AppError> {
+        match self {
+            DocumentPatch::Merge(patch) => json_patch::merge(&mut document, patch),
+            DocumentPatch::Operations(patch) => json_patch::patch(&mut document, patch).map_err(|err| match err.kind {
+                PatchErrorKind::TestFailed => AppError::Conflict(format!("Test of `{}` failed", err.path)),
+                kind => operation_error(err.operation, format!("{}: `{}`", kind, err.path)),
+            })?,
+        }
+        Ok(document)
+    }
+}
+
+impl<T: DeserializeOwned + Validate + 'static> FromRequest for PatchBody<T> {
+    type Error = AppError;
+    type Future = Pin<Box<dyn Future<Output=Result<Self, Self::Error>>>>;
+
+    fn from_request(request: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let essence = request.headers().get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<mime::Mime>().ok())
+            .map(|mime| mime.essence_str().to_string());
+
+        match essence.as_deref() {
+            Some(MERGE_PATCH_JSON) => {
+                let body = Bytes::from_request(request, payload);
+                Box::pin(async move {
+                    let body = body.await.map_err(|err| AppError::BadRequest(err.to_string()))?;
+                    Ok(PatchBody::Document(DocumentPatch::Merge(parse(&body)?)))
+                })
+            }
+            Some(JSON_PATCH_JSON) => {
+                let body = Bytes::from_request(request, payload);
+                Box::pin(async move {
+                    let body = body.await.map_err(|err| AppError::BadRequest(err.to_string()))?;
+                    let patch: Patch = parse(&body)?;
+                    for (index, operation) in patch.0.iter().enumerate() {
+                        if let PatchOperation::Move(_) | PatchOperation::Copy(_) = operation {
+                            return Err(operation_error(index, "only add, remove, replace and test are supported".to_string()));
+                        }
+                    }
+                    Ok(PatchBody::Document(DocumentPatch::Operations(patch)))
+                })
+            }
+            _ => {
+                let json = ValidatedJson::<T>::from_request(request, payload);
+                Box::pin(async move { Ok(PatchBody::Partial(json.await?.into_inner())) })
+            }
+        }
+    }
+}
+
+fn operation_error(index: usize, message: String) -> AppError {
+    AppError::Validation(BTreeMap::from([(format!("[{}]", index), vec![message])]))
+}
@@ -0,0 +1,82 @@
+use crate::utils::errors::AppError;
+use sea_orm::sea_query::NullOrdering;
+use sea_orm::{ColumnTrait, EntityTrait, Order, QueryOrder, Select};
+use std::collections::BTreeMap;
+
+/// One key of a sort spec like `-date_joined`.
+#[derive(Clone, Copy)]
+pub struct SortKey<C> {
+    pub name: &'static str,
+    pub column: C,
+    pub descending: bool,
+}
+
+/// A validated sort spec like `-date_joined,username`. The keys are checked against an
+/// allowlist of `(name, column)` pairs and always end with a unique tie-breaker column, so the
+/// order is total and stable across requests. `NULL`s sort last in either direction.
+#[derive(Clone)]
+pub struct Sort<C> {
+    keys: Vec<SortKey<C>>,
+}
+
+impl<C: ColumnTrait> Sort<C> {
+    pub fn parse(raw: Option<&str>, allowed: &[(&'static str, C)], tie_breaker: (&'static str, C)) -> Result<Self, AppError> {
+        let raw = raw.map(str::trim).filter(|raw| !raw.is_empty()).unwrap_or(tie_breaker.0);
+
+        let mut keys: Vec<SortKey<C>> = vec![];
+        for part in raw.split(',').map(str::trim) {
+            let (name, descending) = match part.strip_prefix('-') {
+                Some(name) => (name, true),
+                None => (part, false),
+            };
+            let (name, column) = allowed
+                .iter()
+                .chain([&tie_breaker])
+                .find(|(allowed_name, _)| *allowed_name == name)
+                .copied()
+                .ok_or_else(|| invalid_sort(&format!("cannot sort by `{}`", name)))?;
+            if keys.iter().any(|key| key.name == name) {
+                return Err(invalid_sort(&format!("`{}` is listed more than once", name)));
+            }
+            keys.push(SortKey { name, column, descending });
+        }
+
+        if !keys.iter().any(|key| key.name == tie_breaker.0) {
+            let descending = keys.last().is_some_and(|key| key.descending);
+            keys.push(SortKey { name: tie_breaker.0, column: tie_breaker.1, descending });
+        }
+
+        Ok(Sort { keys })
+    }
+
+    pub fn keys(&self) -> &[SortKey<C>] {
+        &self.keys
+    }
+
+    /// Orders `select` by the sort keys, or by their exact reverse when `reversed` is set.
+    pub fn apply<E: EntityTrait<Column = C>>(&self, select: Select<E>, reversed: bool) -> Select<E> {
+        self.keys.iter().fold(select, |select, key| {
+            let (order, nulls) = match (key.descending != reversed, reversed) {
+                (false, false) => (Order::Asc, NullOrdering::Last),
+                (true, false) => (Order::Desc, NullOrdering::Last),
+                (false, true) => (Order::Asc, NullOrdering::First),
+                (true, true) => (Order::Desc, NullOrdering::First),
+            };
+            select.order_by_with_nulls(key.column, order, nulls)
+        })
+    }
+}
+
+impl<C> std::fmt::Display for Sort<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keys: Vec<String> = self.keys
+            .iter()
+            .map(|key| format!("{}{}", if key.descending { "-" } else { "" }, key.name))
+            .collect();
+        write!(f, "{}", keys.join(","))
+    }
+}
+
+fn invalid_sort(message: &str) -> AppError {
+    AppError::Validation(BTreeMap::from([("sort".to_string(), vec![message.to_string()])]))
+}
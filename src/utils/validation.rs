@@ -0,0 +1,115 @@
+use crate::utils::errors::AppError;
+use actix_web::dev::Payload;
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::mime;
+use actix_web::web::Bytes;
+use actix_web::{FromRequest, HttpRequest};
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
+use validator::{Validate, ValidationErrors, ValidationErrorsKind};
+
+/// Like `web::Json`, but also runs the `Validate` rules of `T`. Anything the client got wrong,
+/// from a missing field to a weak password, is answered with `422` and the failing fields.
+pub struct ValidatedJson<T>(pub T);
+
+impl<T> ValidatedJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for ValidatedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + Validate + 'static> FromRequest for ValidatedJson<T> {
+    type Error = AppError;
+    type Future = Pin<Box<dyn Future<Output=Result<Self, Self::Error>>>>;
+
+    fn from_request(request: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let is_json = request.headers().get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<mime::Mime>().ok())
+            .is_some_and(|mime| mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON));
+        let body = Bytes::from_request(request, payload);
+
+        Box::pin(async move {
+            if !is_json {
+                return Err(AppError::UnsupportedMediaType);
+            }
+
+            let body = body.await.map_err(|err| AppError::BadRequest(err.to_string()))?;
+            Ok(ValidatedJson(parse_valid::<T>(&body)?))
+        })
+    }
+}
+
+/// Deserializes `body` and runs the `Validate` rules of `T` on it.
+pub fn parse_valid<T: DeserializeOwned + Validate>(body: &[u8]) -> Result<T, AppError> {
+    let value = parse::<T>(body)?;
+    value.validate().map_err(|errors| AppError::Validation(collect_errors(&errors)))?;
+    Ok(value)
+}
+
+/// Deserializes `body`, turning serde errors into the failing field where possible.
+pub fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, AppError> {
+    let deserializer = &mut serde_json::Deserializer::from_slice(body);
+    serde_path_to_error::deserialize(deserializer).map_err(|err| {
+        if err.inner().is_syntax() || err.inner().is_eof() {
+            return AppError::BadRequest(format!("Malformed JSON: {}", err.inner()));
+        }
+
+        let message = err.inner().to_string();
+        let (field, message) = if let Some(field) = quoted_field(&message, "missing field") {
+            (field, "is required".to_string())
+        } else if let Some(field) = quoted_field(&message, "unknown field") {
+            (field, "is not allowed".to_string())
+        } else {
+            let path = err.path().to_string();
+            let message = message.split(" at line ").next().unwrap_or_default().to_string();
+            (if path == "." { "body".to_string() } else { path }, message)
+        };
+
+        let mut errors = BTreeMap::new();
+        errors.insert(field, vec![message]);
+        AppError::Validation(errors)
+    })
+}
+
+// serde reports missing and unknown fields as e.g. "missing field `username`"
+fn quoted_field(message: &str, prefix: &str) -> Option<String> {
+    let rest = message.strip_prefix(prefix)?.trim_start().strip_prefix('`')?;
+    rest.split('`').next().map(str::to_string)
+}
+
+pub fn collect_errors(errors: &ValidationErrors) -> BTreeMap<String, Vec<String>> {
+    let mut collected = BTreeMap::new();
+    collect_into(errors, "", &mut collected);
+    collected
+}
+
+fn collect_into(errors: &ValidationErrors, prefix: &str, collected: &mut BTreeMap<String, Vec<String>>) {
+    for (field, kind) in errors.errors() {
+        let path = if prefix.is_empty() { field.to_string() } else { format!("{}.{}", prefix, field) };
+        match kind {
+            ValidationErrorsKind::Field(field_errors) => {
+                let messages = field_errors.iter()
+                    .map(|error| error.message.as_ref().map(|message| message.to_string()).unwrap_or(error.code.to_string()));
+                collected.entry(path).or_default().extend(messages);
+            }
+            ValidationErrorsKind::Struct(nested) => collect_into(nested, &path, collected),
+            ValidationErrorsKind::List(items) => {
+                for (index, nested) in items {
+                    collect_into(nested, &format!("{}[{}]", path, index), collected);
+                }
+            }
+        }
+    }
+}